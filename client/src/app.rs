@@ -3,24 +3,39 @@ use crate::console::{ConsoleBuilder, ConsoleEvent, ConsoleWindow};
 use crate::login_selector::{EditorSelector, LoginAction};
 use crate::nickname_viewer::{NickNameViewer, NicknameViewerAction};
 use crate::person_selector::{PersonSelector, Selection};
+use crate::server_stats_viewer::ServerStatsViewer;
 use crate::stats_viewer::StatsViewer;
 use common::packets::c2s::{
-    AskForNicknameList, AskForProfilStats, ChangePassword, CommandInput, DeleteNickname, Login,
-    UpdateNicknameProtection, VoteNickname,
+    AskForNicknameList, AskForProfilStats, ChangePassword, CommandInput, CommentNickname,
+    DeleteComment, DeleteNickname, DescribeNickname, Login, RankNicknames, ReactNickname,
+    UpdateNicknameProtection, UpdateProfile, VetoNickname, VoteNickname,
 };
-use common::packets::s2c::{CommandResponse, LoginResponse, NicknameList, ProfilStats};
-use common::Identity;
+use common::packets::s2c::{
+    CommandResponse, LoginResponse, NicknameList, ProfilStats, RankedResults, ServerStats,
+};
+use common::{Identity, ProfilID, VotingMode};
 use eframe::App;
 use egui::{InnerResponse, Rect, TextBuffer};
 use log::warn;
-use std::sync::mpsc;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// a request to perform again later, when the server asked us to slow down
+type Retry = Box<dyn FnOnce(&mut HttpApp) + Send>;
 
 enum IncomingPacket {
     ClassList(LoginResponse),
     NicknameList(NicknameList),
+    RankedResults(RankedResults),
     ProfilStats(ProfilStats),
+    ServerStats(ServerStats),
     CommandResponse(CommandResponse),
+    RetryAfter(Duration, Retry),
+    /// the live channel of the given generation stopped
+    LiveUpdatesClosed(u64),
 }
 
 pub struct HttpApp {
@@ -32,6 +47,16 @@ pub struct HttpApp {
     nickname_viewer: NickNameViewer,
     stats_viewer: StatsViewer,
     console: Option<ConsoleWindow>,
+    server_stats: Option<ServerStatsViewer>,
+    /// requests waiting for their retry time, in egui time
+    delayed_requests: Vec<(f64, Retry)>,
+    /// profil whose nicknames are followed live
+    live_profil: Option<ProfilID>,
+    /// increased on each subscription, so older channels know they have to stop
+    live_generation: Arc<AtomicU64>,
+    /// when the live channel is unavailable, nicknames are fetched periodically instead
+    polling: bool,
+    next_poll: f64,
     ctx: egui::Context,
 }
 
@@ -41,13 +66,16 @@ impl HttpApp {
     #[cfg(target_arch = "wasm32")]
     const ROOT: &'static str = "";
     const UNAUTHORIZED: u16 = 401;
+    const TOO_MANY_REQUESTS: u16 = 429;
+    const POLL_INTERVAL: f64 = 10.0;
 
     fn fetch<T>(&self, request: ehttp::Request, deserializer: T)
     where
-        T: Send + 'static + FnOnce(ehttp::Response) -> Option<IncomingPacket>,
+        T: Send + Clone + 'static + FnOnce(ehttp::Response) -> Option<IncomingPacket>,
     {
         let new_sender = self.sender.clone();
         let ctx = self.ctx.clone();
+        let retry_request = request.clone();
 
         ehttp::fetch(request, move |response| {
             let response = match response {
@@ -67,6 +95,24 @@ impl HttpApp {
                 return;
             }
 
+            // the server is overloaded by us, wait for as long as it asks before trying again
+            if response.status == Self::TOO_MANY_REQUESTS {
+                let delay = response
+                    .headers
+                    .get("retry-after")
+                    .and_then(|delay| delay.trim().parse().ok())
+                    .unwrap_or(1);
+                let retry: Retry = Box::new(move |app| app.fetch(retry_request, deserializer));
+                new_sender
+                    .send(IncomingPacket::RetryAfter(
+                        Duration::from_secs(delay),
+                        retry,
+                    ))
+                    .expect("Failed to channel packet");
+                ctx.request_repaint();
+                return;
+            }
+
             if let Some(packet) = deserializer(response) {
                 let _ = new_sender.send(packet).expect("Failed to channel packet");
                 ctx.request_repaint();
@@ -107,6 +153,13 @@ impl HttpApp {
         self.fetch(request, |_| None);
     }
 
+    fn update_profile(&mut self, update_profile: UpdateProfile) {
+        let request =
+            ehttp::Request::json(format!("{}update_profile", Self::ROOT), &update_profile)
+                .expect("failed_to_create_request");
+        self.fetch(request, Self::LOGIN_RESPONSE_HANDLER);
+    }
+
     fn input_cmd(&mut self, input: CommandInput) {
         let request = ehttp::Request::json(format!("{}cmd_input", Self::ROOT), &input)
             .expect("failed_to_create_request");
@@ -124,6 +177,74 @@ impl HttpApp {
         self.fetch(request, Self::NICKNAME_LIST_HANDLER);
     }
 
+    /// follow the nicknames of a profil as they change, falling back to polling if the server can't push them
+    fn subscribe_nickname_events(&mut self, profil: ProfilID) {
+        let generation = self.live_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let current_generation = self.live_generation.clone();
+        self.live_profil = Some(profil);
+        self.polling = false;
+
+        let sender = self.sender.clone();
+        let ctx = self.ctx.clone();
+        // chunks can be cut anywhere, even in the middle of a character
+        let buffer = Mutex::new(Vec::new());
+
+        let request =
+            ehttp::Request::get(format!("{}nickname_events?profil={}", Self::ROOT, profil.0));
+        ehttp::streaming::fetch(request, move |part| {
+            if current_generation.load(Ordering::Relaxed) != generation {
+                return ControlFlow::Break(());
+            }
+
+            let chunk = match part {
+                Ok(ehttp::streaming::Part::Response(response)) if response.ok => {
+                    return ControlFlow::Continue(())
+                }
+                Ok(ehttp::streaming::Part::Chunk(chunk)) if !chunk.is_empty() => chunk,
+                _ => {
+                    sender
+                        .send(IncomingPacket::LiveUpdatesClosed(generation))
+                        .expect("Failed to channel packet");
+                    ctx.request_repaint();
+                    return ControlFlow::Break(());
+                }
+            };
+
+            let mut buffer = buffer.lock().unwrap();
+            buffer.extend(chunk);
+            // events are separated by an empty line
+            while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                let event: Vec<u8> = buffer.drain(..end + 2).collect();
+                let data: String = String::from_utf8_lossy(&event)
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect();
+                if let Ok(nickname_list) = serde_json::from_str(&data) {
+                    sender
+                        .send(IncomingPacket::NicknameList(nickname_list))
+                        .expect("Failed to channel packet");
+                    ctx.request_repaint();
+                }
+            }
+            ControlFlow::Continue(())
+        });
+    }
+
+    fn poll_nickname_list(&mut self) {
+        let Some(profil) = self.live_profil.filter(|_| self.polling) else {
+            return;
+        };
+
+        let time = self.ctx.input(|i| i.time);
+        if time >= self.next_poll {
+            self.next_poll = time + Self::POLL_INTERVAL;
+            self.request_nickname_list(AskForNicknameList { profil });
+        }
+        self.ctx
+            .request_repaint_after(Duration::from_secs_f64(self.next_poll - time));
+    }
+
     fn request_profil_stats(&mut self, ask_for_person_profil: AskForProfilStats) {
         let request = ehttp::Request::json(
             format!("{}profil_stats", Self::ROOT),
@@ -135,12 +256,75 @@ impl HttpApp {
         });
     }
 
+    fn request_server_stats(&mut self) {
+        let request = ehttp::Request::get(format!("{}server_stats", Self::ROOT));
+        self.fetch(request, |response| {
+            Some(IncomingPacket::ServerStats(response.json().ok()?))
+        });
+    }
+
     fn vote_nickname(&mut self, vote_nickname: VoteNickname) {
         let request = ehttp::Request::json(format!("{}vote_nickname", Self::ROOT), &vote_nickname)
             .expect("Failed to create request");
         self.fetch(request, Self::NICKNAME_LIST_HANDLER);
     }
 
+    fn rank_nicknames(&mut self, rank_nicknames: RankNicknames) {
+        let request =
+            ehttp::Request::json(format!("{}rank_nicknames", Self::ROOT), &rank_nicknames)
+                .expect("Failed to create request");
+        self.fetch(request, |response| {
+            Some(IncomingPacket::RankedResults(response.json().ok()?))
+        });
+    }
+
+    fn request_ranked_results(&mut self, ask_for_nickname_list: AskForNicknameList) {
+        let request = ehttp::Request::json(
+            format!("{}ranked_results", Self::ROOT),
+            &ask_for_nickname_list,
+        )
+        .expect("Failed to create request");
+        self.fetch(request, |response| {
+            Some(IncomingPacket::RankedResults(response.json().ok()?))
+        });
+    }
+
+    fn react_nickname(&mut self, react_nickname: ReactNickname) {
+        let request =
+            ehttp::Request::json(format!("{}react_nickname", Self::ROOT), &react_nickname)
+                .expect("Failed to create request");
+        self.fetch(request, Self::NICKNAME_LIST_HANDLER);
+    }
+
+    fn describe_nickname(&mut self, describe_nickname: DescribeNickname) {
+        let request = ehttp::Request::json(
+            format!("{}describe_nickname", Self::ROOT),
+            &describe_nickname,
+        )
+        .expect("Failed to create request");
+        self.fetch(request, Self::NICKNAME_LIST_HANDLER);
+    }
+
+    fn comment_nickname(&mut self, comment_nickname: CommentNickname) {
+        let request =
+            ehttp::Request::json(format!("{}comment_nickname", Self::ROOT), &comment_nickname)
+                .expect("Failed to create request");
+        self.fetch(request, Self::NICKNAME_LIST_HANDLER);
+    }
+
+    fn delete_comment(&mut self, delete_comment: DeleteComment) {
+        let request =
+            ehttp::Request::json(format!("{}delete_comment", Self::ROOT), &delete_comment)
+                .expect("Failed to create request");
+        self.fetch(request, Self::NICKNAME_LIST_HANDLER);
+    }
+
+    fn veto_nickname(&mut self, veto_nickname: VetoNickname) {
+        let request = ehttp::Request::json(format!("{}veto_nickname", Self::ROOT), &veto_nickname)
+            .expect("Failed to create request");
+        self.fetch(request, Self::NICKNAME_LIST_HANDLER);
+    }
+
     fn delete_nickname(&mut self, delete_nickname: DeleteNickname) {
         let request =
             ehttp::Request::json(format!("{}delete_nickname", Self::ROOT), &delete_nickname)
@@ -159,6 +343,9 @@ impl HttpApp {
 
     fn check_incoming(&mut self) {
         let mut should_update_viewed_profil = false;
+        let mut should_update_server_stats = false;
+        // the tally changes with the nicknames
+        let mut ranked_profiles = vec![];
 
         for message in self.incoming_message.try_iter() {
             match message {
@@ -167,7 +354,11 @@ impl HttpApp {
                         logged,
                         allowed_to_use_cmd,
                         mut classes,
+                        display_name,
+                        bio,
+                        refusal,
                     } = class_list;
+                    self.editor_selector.set_profile(display_name, bio, refusal);
                     self.class_selector.set_classes(
                         classes
                             .iter_mut()
@@ -193,26 +384,66 @@ impl HttpApp {
                     } else {
                         None
                     };
+                    self.server_stats = allowed_to_use_cmd.then(ServerStatsViewer::default);
+                    should_update_server_stats = allowed_to_use_cmd;
                 }
                 IncomingPacket::NicknameList(person_profil_response) => {
+                    if person_profil_response.voting_mode == VotingMode::Ranked {
+                        ranked_profiles.push(person_profil_response.profil_id);
+                    }
                     self.nickname_viewer.set_profil(person_profil_response)
                 }
+                IncomingPacket::RankedResults(results) => self.nickname_viewer.set_results(results),
                 IncomingPacket::ProfilStats(stats) => self.stats_viewer.set_stats(stats),
+                IncomingPacket::ServerStats(stats) => {
+                    if let Some(viewer) = &mut self.server_stats {
+                        viewer.set_stats(stats)
+                    }
+                }
                 IncomingPacket::CommandResponse(CommandResponse { text }) => {
                     if let Some(console) = &mut self.console {
                         console.write(&text);
                         console.prompt();
                     }
                 }
+                IncomingPacket::RetryAfter(delay, retry) => {
+                    let time = self.ctx.input(|i| i.time) + delay.as_secs_f64();
+                    self.delayed_requests.push((time, retry));
+                    self.ctx.request_repaint_after(delay);
+                }
+                IncomingPacket::LiveUpdatesClosed(generation) => {
+                    if generation == self.live_generation.load(Ordering::Relaxed) {
+                        self.polling = true;
+                        self.next_poll = 0.0;
+                    }
+                }
             }
         }
+
+        let time = self.ctx.input(|i| i.time);
+        let (ready, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed_requests)
+            .into_iter()
+            .partition(|(retry_time, _)| *retry_time <= time);
+        self.delayed_requests = delayed;
+        for (_, retry) in ready {
+            retry(self);
+        }
+
         if let Some(profil) = self
             .person_selector
             .get_selected_profil()
             .filter(|_| should_update_viewed_profil)
         {
-            self.request_nickname_list(AskForNicknameList { profil })
+            // the identity may have changed, the channel has to be opened again
+            self.subscribe_nickname_events(profil)
+        }
+        if should_update_server_stats {
+            self.request_server_stats()
+        }
+        for profil in ranked_profiles {
+            self.request_ranked_results(AskForNicknameList { profil });
         }
+        self.poll_nickname_list();
     }
 
     pub fn new(ctx: &eframe::CreationContext) -> Self {
@@ -229,6 +460,12 @@ impl HttpApp {
             nickname_viewer: Default::default(),
             stats_viewer: Default::default(),
             console: None,
+            server_stats: None,
+            delayed_requests: vec![],
+            live_profil: None,
+            live_generation: Default::default(),
+            polling: false,
+            next_poll: 0.0,
             ctx,
         };
         this.request_class_list();
@@ -275,6 +512,7 @@ impl App for HttpApp {
                     }
                     self.change_password(password)
                 }
+                LoginAction::UpdateProfile(update_profile) => self.update_profile(update_profile),
                 _ => (),
             }
 
@@ -305,6 +543,21 @@ impl App for HttpApp {
             }
         }
 
+        if let Some(server_stats) = &self.server_stats {
+            let inner = egui::Window::new("Statistiques serveur")
+                .default_open(false)
+                .constrain_to(Rect::everything_below(spacing))
+                .resizable(true)
+                .show(ctx, |ui| server_stats.update(ui));
+
+            if let Some(InnerResponse {
+                inner: Some(true), ..
+            }) = inner
+            {
+                self.request_server_stats()
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let Some(selected_class) = self.class_selector.get_selected() else {
                 return;
@@ -314,9 +567,7 @@ impl App for HttpApp {
             let requested_profiles = self.person_selector.update(ui, selected_class);
             if let Some(profil) = requested_profiles {
                 match profil {
-                    Selection::ViewNickname(profil) => {
-                        self.request_nickname_list(AskForNicknameList { profil })
-                    }
+                    Selection::ViewNickname(profil) => self.subscribe_nickname_events(profil),
                     Selection::ViewData(profil) => {
                         self.request_profil_stats(AskForProfilStats { profil })
                     }
@@ -333,6 +584,24 @@ impl App for HttpApp {
                             NicknameViewerAction::Vote(vote_nickname) => {
                                 self.vote_nickname(vote_nickname)
                             }
+                            NicknameViewerAction::Rank(rank_nicknames) => {
+                                self.rank_nicknames(rank_nicknames)
+                            }
+                            NicknameViewerAction::React(react_nickname) => {
+                                self.react_nickname(react_nickname)
+                            }
+                            NicknameViewerAction::Describe(describe_nickname) => {
+                                self.describe_nickname(describe_nickname)
+                            }
+                            NicknameViewerAction::Comment(comment_nickname) => {
+                                self.comment_nickname(comment_nickname)
+                            }
+                            NicknameViewerAction::DeleteComment(delete_comment) => {
+                                self.delete_comment(delete_comment)
+                            }
+                            NicknameViewerAction::Veto(veto_nickname) => {
+                                self.veto_nickname(veto_nickname)
+                            }
                             NicknameViewerAction::UpdateProtection(update) => {
                                 self.update_nickname_protection(update)
                             }
@@ -5,6 +5,8 @@ mod login_selector;
 mod nickname_viewer;
 mod password_selector;
 mod person_selector;
+mod profile_editor;
+mod server_stats_viewer;
 mod stats_viewer;
 
 pub use app::HttpApp;
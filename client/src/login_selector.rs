@@ -1,4 +1,7 @@
 use crate::password_selector::{PasswordSelector, Response};
+use crate::profile_editor::{self, ProfileEditor};
+use common::packets::c2s::UpdateProfile;
+use common::packets::s2c::ProfileRefusal;
 use common::Identity;
 
 /// Login field used on top of the UI
@@ -7,12 +10,19 @@ pub struct EditorSelector {
     password: String,
     logged: bool,
     change_password: Option<PasswordSelector>,
+    /// name shown to the others, the login name is used until the server sends it
+    display_name: Option<String>,
+    bio: Option<String>,
+    edit_profile: Option<ProfileEditor>,
+    /// the editor stays open until the server accepts the update
+    profile_update_pending: bool,
 }
 
 pub enum LoginAction {
     Login,
     Logout,
     ChangePassword(String),
+    UpdateProfile(UpdateProfile),
     None,
 }
 
@@ -32,6 +42,10 @@ impl EditorSelector {
                 .unwrap_or(String::new()),
             logged: false,
             change_password: None,
+            display_name: None,
+            bio: None,
+            edit_profile: None,
+            profile_update_pending: false,
         }
     }
 
@@ -39,18 +53,65 @@ impl EditorSelector {
         self.logged = logged;
     }
 
+    pub fn set_profile(
+        &mut self,
+        display_name: Option<String>,
+        bio: Option<String>,
+        refusal: Option<ProfileRefusal>,
+    ) {
+        self.display_name = display_name;
+        self.bio = bio;
+        if std::mem::take(&mut self.profile_update_pending) {
+            match (&mut self.edit_profile, refusal) {
+                (Some(editor), Some(_)) => editor.set_refusal(refusal),
+                _ => self.edit_profile = None,
+            }
+        }
+    }
+
     fn display_logout(&mut self, ui: &mut egui::Ui) -> bool {
-        ui.label(format!("Connecté.e en tant que {}", self.name));
+        ui.label(format!(
+            "Connecté.e en tant que {}",
+            self.display_name.as_ref().unwrap_or(&self.name)
+        ));
+        if let Some(bio) = &self.bio {
+            ui.label(bio);
+        }
         ui.horizontal(|ui| {
             let r = ui.button("Se déconnecter").clicked();
             if self.change_password.is_none() && ui.button("Modifier le mot de passe").clicked() {
                 self.change_password = Some(PasswordSelector::default());
             }
+            if self.edit_profile.is_none() && ui.button("Modifier le profil").clicked() {
+                self.edit_profile = Some(ProfileEditor::new(
+                    self.display_name.as_deref(),
+                    self.bio.as_deref(),
+                ));
+            }
             r
         })
         .inner
     }
 
+    fn display_profile_editor(&mut self, ui: &mut egui::Ui) -> LoginAction {
+        match self
+            .edit_profile
+            .as_mut()
+            .map(|editor| editor.display(ui))
+            .unwrap_or(profile_editor::Response::None)
+        {
+            profile_editor::Response::None => LoginAction::None,
+            profile_editor::Response::Back => {
+                self.edit_profile = None;
+                LoginAction::None
+            }
+            profile_editor::Response::Changed(update) => {
+                self.profile_update_pending = true;
+                LoginAction::UpdateProfile(update)
+            }
+        }
+    }
+
     pub fn update(&mut self, ui: &mut egui::Ui) -> LoginAction {
         if self.logged {
             if self.display_logout(ui) {
@@ -62,7 +123,7 @@ impl EditorSelector {
                     .map(|a| a.display(ui))
                     .unwrap_or(Response::None)
                 {
-                    Response::None => LoginAction::None,
+                    Response::None => self.display_profile_editor(ui),
                     Response::Back => {
                         self.change_password = None;
                         LoginAction::None
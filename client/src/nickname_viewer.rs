@@ -1,7 +1,10 @@
-use common::packets::c2s::{DeleteNickname, UpdateNicknameProtection, VoteNickname};
+use common::packets::c2s::{
+    CommentNickname, DeleteComment, DeleteNickname, DescribeNickname, RankNicknames, ReactNickname,
+    UpdateNicknameProtection, VetoNickname, VoteNickname,
+};
 use common::packets::s2c;
-use common::packets::s2c::NicknameStatut;
-use common::ProfilID;
+use common::packets::s2c::{Comment, NicknameStatut, Refusal};
+use common::{ProfilID, Reaction, VoteVisibility, VotingMode};
 use egui::RichText;
 use egui::TextBuffer;
 use std::collections::HashMap;
@@ -9,13 +12,39 @@ use std::collections::HashMap;
 struct Profile {
     allowed_to_vote: bool,
     allowed_to_protect: bool,
+    allowed_to_comment: bool,
+    allowed_to_react: bool,
     nicknames: Vec<NicknameStatut>,
+    /// why the last action was refused
+    refusal: Option<Refusal>,
+    voting_mode: VotingMode,
+    /// 0 during the open proposal period
+    round: u32,
+    visibility: VoteVisibility,
+    /// counts are hidden until the results are revealed
+    blind: bool,
+    /// only for your own profil, when vetoes are allowed
+    vetoes_left: Option<u32>,
+}
+
+/// ballot of the requester in the ranked mode, edited locally until it is sent
+#[derive(Default)]
+struct Ranking {
+    results: s2c::RankedResults,
+    ballot: Vec<String>,
+    edited: bool,
 }
 
 pub enum NicknameViewerAction {
     Vote(VoteNickname),
+    Rank(RankNicknames),
     Delete(DeleteNickname),
+    React(ReactNickname),
+    Describe(DescribeNickname),
+    Comment(CommentNickname),
+    DeleteComment(DeleteComment),
     UpdateProtection(UpdateNicknameProtection),
+    Veto(VetoNickname),
     None,
 }
 
@@ -23,8 +52,14 @@ pub enum NicknameViewerAction {
 pub struct NickNameViewer {
     /// contain the profil
     profiles: HashMap<ProfilID, Profile>,
+    /// only for the profiles in the ranked mode
+    rankings: HashMap<ProfilID, Ranking>,
     /// edition field for a nickname proposition
     new_nickname: String,
+    /// edition fields for the comments, by nickname
+    comment_drafts: HashMap<String, String>,
+    /// edition fields for the descriptions of your nicknames
+    description_drafts: HashMap<String, String>,
 }
 
 impl NickNameViewer {
@@ -35,6 +70,14 @@ impl NickNameViewer {
             mut nicknames,
             allowed_to_vote,
             allowed_to_protect,
+            allowed_to_comment,
+            allowed_to_react,
+            refusal,
+            voting_mode,
+            round,
+            visibility,
+            blind,
+            vetoes_left,
         } = profil;
 
         //always sort by the most voted !
@@ -47,11 +90,129 @@ impl NickNameViewer {
             Profile {
                 allowed_to_vote,
                 allowed_to_protect,
+                allowed_to_comment,
+                allowed_to_react,
                 nicknames,
+                refusal,
+                voting_mode,
+                round,
+                visibility,
+                blind,
+                vetoes_left,
             },
         );
     }
 
+    /// used to cache the tally of a profil in the ranked mode
+    pub fn set_results(&mut self, results: s2c::RankedResults) {
+        let ranking = self.rankings.entry(results.profil_id).or_default();
+        // keep the changes the user did not send yet
+        if !ranking.edited {
+            ranking.ballot = results.your_ranking.clone();
+        }
+        ranking.results = results;
+    }
+
+    fn refusal_text(refusal: Refusal) -> &'static str {
+        match refusal {
+            Refusal::NotAllowed => "Vous n'avez pas le droit de faire ça",
+            Refusal::SelfVote => "Vous ne pouvez pas voter pour vos propres surnoms",
+            Refusal::NoVotesLeft => "Vous n'avez plus de votes disponibles",
+            Refusal::NotRanked => "Le vote par classement n'est pas activé",
+            Refusal::Frozen => "Les propositions sont closes pour la finale",
+            Refusal::CommentTooLong => "Ce commentaire est trop long",
+            Refusal::ThreadFull => "Cette discussion a atteint le nombre maximum de commentaires",
+            Refusal::DescriptionTooLong => "Cette description est trop longue",
+            Refusal::Vetoed => "Ce surnom a été refusé",
+            Refusal::NoVetoesLeft => "Vous n'avez plus de vetos disponibles",
+        }
+    }
+
+    /// results of the tally and drag and drop edition of the ballot
+    fn update_ranking(
+        ui: &mut egui::Ui,
+        ranking: &mut Ranking,
+        allowed_to_vote: bool,
+        id: ProfilID,
+    ) -> NicknameViewerAction {
+        let mut action = NicknameViewerAction::None;
+        let results = &ranking.results;
+
+        ui.separator();
+        match &results.winner {
+            Some(winner) => ui.heading(format!("En tête : {winner}")),
+            None => ui.heading("Pas encore de gagnant"),
+        };
+        egui::CollapsingHeader::new(format!("{} bulletin(s)", results.ballots)).show(ui, |ui| {
+            for (round, counts) in results.rounds.iter().enumerate() {
+                let counts: Vec<_> = counts.iter().map(|(n, c)| format!("{n} ({c})")).collect();
+                ui.label(format!("Tour {} : {}", round + 1, counts.join(", ")));
+            }
+            let borda: Vec<_> = results
+                .borda
+                .iter()
+                .map(|(n, s)| format!("{n} ({s})"))
+                .collect();
+            ui.label(format!("Borda : {}", borda.join(", ")));
+        });
+        if let Some(refusal) = results.refusal {
+            ui.label(
+                RichText::new(Self::refusal_text(refusal))
+                    .color(egui::Color32::from_rgb(255, 100, 100)),
+            );
+        }
+
+        if !allowed_to_vote {
+            return action;
+        }
+        ui.heading("Votre classement");
+        ui.label("Glissez les surnoms pour les réordonner");
+        let mut moved = None;
+        let mut removed = None;
+        for (rank, nickname) in ranking.ballot.iter().enumerate() {
+            let (_, dropped) = ui.dnd_drop_zone::<usize, _>(egui::Frame::default(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.dnd_drag_source(egui::Id::new(("ranking", id.0, rank)), rank, |ui| {
+                        ui.label(format!("{}. {nickname}", rank + 1));
+                    });
+                    if ui.small_button("Retirer").clicked() {
+                        removed = Some(rank);
+                    }
+                });
+            });
+            if let Some(from) = dropped {
+                moved = Some((*from, rank));
+            }
+        }
+        if let Some((from, to)) = moved {
+            let nickname = ranking.ballot.remove(from);
+            ranking.ballot.insert(to, nickname);
+            ranking.edited = true;
+        }
+        if let Some(rank) = removed {
+            ranking.ballot.remove(rank);
+            ranking.edited = true;
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(ranking.edited, egui::Button::new("Envoyer le classement"))
+                .clicked()
+            {
+                ranking.edited = false;
+                action = NicknameViewerAction::Rank(RankNicknames {
+                    target: id,
+                    ranking: ranking.ballot.clone(),
+                });
+            }
+            if ranking.edited && ui.button("Annuler").clicked() {
+                ranking.ballot = ranking.results.your_ranking.clone();
+                ranking.edited = false;
+            }
+        });
+        action
+    }
+
     pub fn update(&mut self, ui: &mut egui::Ui, id: ProfilID) -> NicknameViewerAction {
         let mut action = NicknameViewerAction::None;
 
@@ -59,10 +220,52 @@ impl NickNameViewer {
             return action;
         };
 
+        if profil.round > 0 {
+            ui.heading(format!("Finale, tour {}", profil.round + 1));
+        }
+        if profil.visibility == VoteVisibility::Public {
+            ui.label("Les votes sont publics");
+        }
+        if profil.blind {
+            ui.label("Les résultats sont cachés jusqu'à leur révélation");
+        }
+        if let Some(vetoes_left) = profil.vetoes_left {
+            ui.label(format!("Vetos restants : {vetoes_left}"));
+        }
+
+        // votes or points still available, depending on the voting mode
+        let used: u32 = profil.nicknames.iter().map(|n| n.your_votes).sum();
+        let left = match profil.voting_mode {
+            VotingMode::Single | VotingMode::Ranked => 1,
+            VotingMode::Approval { max_votes } => {
+                ui.label(format!(
+                    "Votes restants : {}/{max_votes}",
+                    max_votes.saturating_sub(used)
+                ));
+                max_votes.saturating_sub(used)
+            }
+            VotingMode::Points { points } => {
+                ui.label(format!(
+                    "Points restants : {}/{points}",
+                    points.saturating_sub(used)
+                ));
+                points.saturating_sub(used)
+            }
+        };
+        let mut ranking = self.rankings.get_mut(&id);
+        let vote = |nickname: &String, points: Option<u32>| {
+            NicknameViewerAction::Vote(VoteNickname {
+                nickname: nickname.clone(),
+                target: id,
+                points,
+            })
+        };
+
         egui::ScrollArea::both().show(ui, |ui| {
             egui::Grid::new("nicknames").striped(true).show(ui, |ui| {
                 ui.heading("Surnoms");
                 ui.heading("Votes");
+                ui.heading("Réactions");
                 ui.end_row();
 
                 for NicknameStatut {
@@ -71,9 +274,22 @@ impl NickNameViewer {
                     contain_you,
                     allowed_to_be_delete,
                     protected,
+                    your_votes,
+                    voters,
+                    description,
+                    reactions,
+                    your_reactions,
+                    vetoed,
+                    ..
                 } in profil.nicknames.iter()
                 {
-                    ui.label(proposition);
+                    let label = match vetoed {
+                        true => ui.label(RichText::new(proposition).strikethrough()),
+                        false => ui.label(proposition),
+                    };
+                    if let Some(description) = description {
+                        label.on_hover_text(description);
+                    }
 
                     let color = if *contain_you {
                         egui::Color32::from_rgb(255, 100, 100)
@@ -81,14 +297,85 @@ impl NickNameViewer {
                         egui::Color32::from_rgb(100, 100, 255)
                     };
 
-                    ui.label(RichText::new(count.to_string()).color(color));
+                    let count = match profil.blind {
+                        true => "?".to_string(),
+                        false => count.to_string(),
+                    };
+                    let count = ui.label(RichText::new(count).color(color));
+                    if !voters.is_empty() {
+                        count.on_hover_text(voters.join(", "));
+                    }
 
-                    if profil.allowed_to_vote && ui.button("Voter").clicked() {
-                        //lazy evaluation hide the button if your not in the list
-                        action = NicknameViewerAction::Vote(VoteNickname {
-                            nickname: proposition.clone(),
-                            target: id,
-                        });
+                    ui.horizontal(|ui| {
+                        for reaction in Reaction::ALL {
+                            let count = reactions
+                                .iter()
+                                .find(|(r, _)| *r == reaction)
+                                .map_or(0, |(_, count)| *count);
+                            let text = match count {
+                                0 => reaction.emoji().to_string(),
+                                count => format!("{} {count}", reaction.emoji()),
+                            };
+                            if profil.allowed_to_react {
+                                let yours = your_reactions.contains(&reaction);
+                                if ui.selectable_label(yours, text).clicked() {
+                                    action = NicknameViewerAction::React(ReactNickname {
+                                        target: id,
+                                        nickname: proposition.clone(),
+                                        reaction,
+                                    });
+                                }
+                            } else if count > 0 {
+                                ui.label(text);
+                            }
+                        }
+                    });
+
+                    //lazy evaluation hide the buttons if your not in the list
+                    if profil.allowed_to_vote && !vetoed {
+                        match profil.voting_mode {
+                            VotingMode::Single => {
+                                if ui.button("Voter").clicked() {
+                                    action = vote(proposition, None);
+                                }
+                            }
+                            VotingMode::Approval { .. } => {
+                                if *your_votes > 0 {
+                                    if ui.button("Retirer").clicked() {
+                                        action = vote(proposition, None);
+                                    }
+                                } else if ui
+                                    .add_enabled(left > 0, egui::Button::new("Voter"))
+                                    .clicked()
+                                {
+                                    action = vote(proposition, None);
+                                }
+                            }
+                            VotingMode::Points { .. } => {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add_enabled(*your_votes > 0, egui::Button::new("-"))
+                                        .clicked()
+                                    {
+                                        action = vote(proposition, Some(your_votes - 1));
+                                    }
+                                    ui.label(your_votes.to_string());
+                                    if ui.add_enabled(left > 0, egui::Button::new("+")).clicked() {
+                                        action = vote(proposition, Some(your_votes + 1));
+                                    }
+                                });
+                            }
+                            VotingMode::Ranked => {
+                                if let Some(ranking) =
+                                    ranking.as_mut().filter(|r| !r.ballot.contains(proposition))
+                                {
+                                    if ui.button("Classer").clicked() {
+                                        ranking.ballot.push(proposition.clone());
+                                        ranking.edited = true;
+                                    }
+                                }
+                            }
+                        }
                     }
 
                     if *allowed_to_be_delete && ui.button("Supprimer").clicked() {
@@ -98,6 +385,21 @@ impl NickNameViewer {
                         });
                     }
 
+                    if let Some(vetoes_left) = profil.vetoes_left {
+                        let result = if *vetoed {
+                            ui.button("Lever le veto")
+                        } else {
+                            ui.add_enabled(vetoes_left > 0 && !protected, egui::Button::new("Veto"))
+                        };
+                        if result.clicked() {
+                            action = NicknameViewerAction::Veto(VetoNickname {
+                                target: id,
+                                nickname: proposition.clone(),
+                                vetoed: !vetoed,
+                            });
+                        }
+                    }
+
                     if profil.allowed_to_protect {
                         let result = if *protected {
                             ui.button("Déverrouiller")
@@ -117,9 +419,108 @@ impl NickNameViewer {
                     ui.end_row();
                 }
             });
+
+            // only the nicknames with something to show or to edit get a row
+            let has_details = |nickname: &NicknameStatut| {
+                profil.allowed_to_comment
+                    || nickname.allowed_to_describe
+                    || nickname.description.is_some()
+                    || !nickname.comments.is_empty()
+            };
+            if profil.nicknames.iter().any(has_details) {
+                ui.separator();
+                ui.heading("Discussions");
+            }
+            for nickname in profil.nicknames.iter().filter(|n| has_details(n)) {
+                let title = format!("{} ({})", nickname.proposition, nickname.comments.len());
+                egui::CollapsingHeader::new(title)
+                    .id_salt(("comments", id.0, &nickname.proposition))
+                    .show(ui, |ui| {
+                        if let Some(description) = &nickname.description {
+                            ui.label(RichText::new(description).italics());
+                        }
+                        if nickname.allowed_to_describe {
+                            let draft = self
+                                .description_drafts
+                                .entry(nickname.proposition.clone())
+                                .or_insert_with(|| {
+                                    nickname.description.clone().unwrap_or_default()
+                                });
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(draft)
+                                        .hint_text("Origine du surnom")
+                                        .char_limit(NicknameStatut::DESCRIPTION_MAX_LENGTH),
+                                );
+                                if ui.button("Enregistrer").clicked() {
+                                    action = NicknameViewerAction::Describe(DescribeNickname {
+                                        target: id,
+                                        nickname: nickname.proposition.clone(),
+                                        description: draft.clone(),
+                                    });
+                                }
+                            });
+                        }
+
+                        for comment in nickname.comments.iter() {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label(RichText::new(&comment.author).strong());
+                                ui.label(&comment.text);
+                                if comment.allowed_to_be_delete
+                                    && ui.small_button("Supprimer").clicked()
+                                {
+                                    action = NicknameViewerAction::DeleteComment(DeleteComment {
+                                        target: id,
+                                        nickname: nickname.proposition.clone(),
+                                        comment: comment.id,
+                                    });
+                                }
+                            });
+                        }
+
+                        if profil.allowed_to_comment
+                            && nickname.comments.len() < Comment::MAX_PER_THREAD
+                        {
+                            let draft = self
+                                .comment_drafts
+                                .entry(nickname.proposition.clone())
+                                .or_default();
+                            ui.horizontal(|ui| {
+                                let submitted = ui.button("Commenter").clicked();
+                                let pressed_enter = ui
+                                    .add(
+                                        egui::TextEdit::singleline(draft)
+                                            .hint_text("Nouveau commentaire")
+                                            .char_limit(Comment::MAX_LENGTH),
+                                    )
+                                    .lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                if submitted || pressed_enter {
+                                    action = NicknameViewerAction::Comment(CommentNickname {
+                                        target: id,
+                                        nickname: nickname.proposition.clone(),
+                                        text: draft.take(),
+                                    });
+                                }
+                            });
+                        }
+                    });
+            }
         });
 
-        if profil.allowed_to_vote {
+        if let Some(refusal) = profil.refusal {
+            ui.label(
+                RichText::new(Self::refusal_text(refusal))
+                    .color(egui::Color32::from_rgb(255, 100, 100)),
+            );
+        }
+
+        if let Some(ranking) = ranking.filter(|_| profil.voting_mode == VotingMode::Ranked) {
+            action = Self::update_ranking(ui, ranking, profil.allowed_to_vote, id);
+        }
+
+        // the final ballot is frozen
+        if profil.allowed_to_vote && profil.round == 0 {
             ui.horizontal(|ui| {
                 let submitted = ui.button("Proposer").clicked();
                 let pressed_enter = ui
@@ -131,10 +532,7 @@ impl NickNameViewer {
                     .lost_focus()
                     && ui.input(|i| i.key_pressed(egui::Key::Enter));
                 if submitted || pressed_enter {
-                    action = NicknameViewerAction::Vote(VoteNickname {
-                        nickname: self.new_nickname.take(),
-                        target: id,
-                    });
+                    action = vote(&self.new_nickname.take(), None);
                 }
             });
         }
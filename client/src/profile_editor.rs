@@ -0,0 +1,72 @@
+use common::packets::c2s::UpdateProfile;
+use common::packets::s2c::ProfileRefusal;
+use egui::{RichText, TextEdit, Widget};
+
+/// Display name and bio shown to the others, the login name stays the same
+#[derive(Default)]
+pub struct ProfileEditor {
+    display_name: String,
+    bio: String,
+    refusal: Option<ProfileRefusal>,
+}
+
+pub enum Response {
+    None,
+    Back,
+    Changed(UpdateProfile),
+}
+
+impl ProfileEditor {
+    pub fn new(display_name: Option<&str>, bio: Option<&str>) -> Self {
+        Self {
+            display_name: display_name.unwrap_or_default().to_string(),
+            bio: bio.unwrap_or_default().to_string(),
+            refusal: None,
+        }
+    }
+
+    pub fn set_refusal(&mut self, refusal: Option<ProfileRefusal>) {
+        self.refusal = refusal;
+    }
+
+    fn refusal_text(refusal: ProfileRefusal) -> &'static str {
+        match refusal {
+            ProfileRefusal::DisplayNameTooLong => "Ce nom est trop long",
+            ProfileRefusal::DisplayNameTaken => "Ce nom est déjà utilisé",
+            ProfileRefusal::BioTooLong => "Cette bio est trop longue",
+        }
+    }
+
+    pub fn display(&mut self, ui: &mut egui::Ui) -> Response {
+        ui.label("Modifier le profil");
+        TextEdit::singleline(&mut self.display_name)
+            .hint_text("Nom affiché")
+            .char_limit(UpdateProfile::DISPLAY_NAME_MAX_LENGTH)
+            .ui(ui);
+        TextEdit::multiline(&mut self.bio)
+            .hint_text("Bio")
+            .desired_rows(2)
+            .char_limit(UpdateProfile::BIO_MAX_LENGTH)
+            .ui(ui);
+        if let Some(refusal) = self.refusal {
+            ui.label(
+                RichText::new(Self::refusal_text(refusal))
+                    .color(egui::Color32::from_rgb(255, 100, 100)),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Confirmer").clicked() {
+                return Response::Changed(UpdateProfile {
+                    display_name: self.display_name.clone(),
+                    bio: self.bio.clone(),
+                });
+            };
+            if ui.button("Annuler").clicked() {
+                return Response::Back;
+            }
+            Response::None
+        })
+        .inner
+    }
+}
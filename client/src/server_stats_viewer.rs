@@ -0,0 +1,65 @@
+use common::packets::s2c::ServerStats;
+
+/// Admin overview of the whole deployment
+#[derive(Default)]
+pub struct ServerStatsViewer {
+    stats: Option<ServerStats>,
+}
+
+impl ServerStatsViewer {
+    pub fn set_stats(&mut self, stats: ServerStats) {
+        self.stats = Some(stats);
+    }
+
+    /// return true when a refresh is requested
+    pub fn update(&self, ui: &mut egui::Ui) -> bool {
+        let refresh = ui.button("Actualiser").clicked();
+
+        let Some(stats) = &self.stats else {
+            ui.spinner();
+            return refresh;
+        };
+
+        egui::Grid::new("server_stats")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Profils");
+                ui.label(stats.total_profiles.to_string());
+                ui.end_row();
+
+                ui.label("Classes");
+                ui.label(stats.total_classes.to_string());
+                ui.end_row();
+
+                ui.label("Surnoms proposés");
+                ui.label(stats.total_propositions.to_string());
+                ui.end_row();
+
+                ui.label("Votes");
+                ui.label(stats.total_votes.to_string());
+                ui.end_row();
+
+                ui.label("Votants actifs (24h)");
+                ui.label(stats.daily_active_voters.to_string());
+                ui.end_row();
+
+                ui.label("Jamais connecté.e.s");
+                ui.label(stats.never_logged_in.to_string());
+                ui.end_row();
+
+                ui.label("Sans classe");
+                ui.label(stats.lonely_people.len().to_string());
+                ui.end_row();
+            });
+
+        if !stats.lonely_people.is_empty() {
+            ui.collapsing("Personnes sans classe", |ui| {
+                for name in stats.lonely_people.iter() {
+                    ui.label(name);
+                }
+            });
+        }
+
+        refresh
+    }
+}
@@ -17,6 +17,10 @@ impl StatsViewer {
         let Some(stats) = self.profiles.get(&id) else {
             return;
         };
+        ui.heading(&stats.display_name);
+        if let Some(bio) = &stats.bio {
+            ui.label(bio);
+        }
         egui::Grid::new("nicknames").striped(true).show(ui, |ui| {
             ui.label("Classe(s)");
             ui.label(stats.numbers_of_classes.to_string());
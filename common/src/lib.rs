@@ -10,6 +10,56 @@ pub struct ProfilID(pub u32);
 #[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ClassID(pub u32);
 
+/// How votes are cast on the nicknames of a class
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum VotingMode {
+    /// a single vote per target, voting again moves it
+    #[default]
+    Single,
+    /// vote for up to `max_votes` nicknames per target, voting again removes the vote
+    Approval { max_votes: u32 },
+    /// distribute `points` between the nicknames of a target
+    Points { points: u32 },
+    /// rank the nicknames of a target, tallied by instant-runoff
+    Ranked,
+}
+
+/// What people can see of the votes of a class, ordered from the most private
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum VoteVisibility {
+    #[default]
+    Counts,
+    /// the names of the voters are shown
+    Public,
+}
+
+/// Lightweight reaction on a nickname, it doesn't count as a vote
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Reaction {
+    Laugh,
+    Heart,
+    Fire,
+    Skull,
+}
+
+impl Reaction {
+    pub const ALL: [Reaction; 4] = [
+        Reaction::Laugh,
+        Reaction::Heart,
+        Reaction::Fire,
+        Reaction::Skull,
+    ];
+
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            Reaction::Laugh => "😂",
+            Reaction::Heart => "❤",
+            Reaction::Fire => "🔥",
+            Reaction::Skull => "💀",
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default, Hash, Eq, PartialEq)]
 /// Used to log in
 pub struct Identity {
@@ -1,5 +1,5 @@
 pub mod c2s {
-    use crate::{Identity, ProfilID};
+    use crate::{Identity, ProfilID, Reaction};
     use serde::{Deserialize, Serialize};
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -12,6 +12,19 @@ pub mod c2s {
         pub new_password: String,
     }
 
+    /// Public label and bio of the requester, the login name is unchanged.
+    /// An empty display name falls back to the login name, an empty bio removes it
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct UpdateProfile {
+        pub display_name: String,
+        pub bio: String,
+    }
+
+    impl UpdateProfile {
+        pub const DISPLAY_NAME_MAX_LENGTH: usize = 30;
+        pub const BIO_MAX_LENGTH: usize = 200;
+    }
+
     #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct CommandInput {
         pub text: String,
@@ -22,6 +35,56 @@ pub mod c2s {
     pub struct VoteNickname {
         pub target: ProfilID,
         pub nickname: String,
+        /// points to give to this nickname in the points mode, 1 when absent
+        #[serde(default)]
+        pub points: Option<u32>,
+    }
+
+    /// Replace the ballot of the requester, in the ranked mode
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct RankNicknames {
+        pub target: ProfilID,
+        /// from the preferred nickname
+        pub ranking: Vec<String>,
+    }
+
+    /// Add the reaction of the requester, or remove it if already there
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct ReactNickname {
+        pub target: ProfilID,
+        pub nickname: String,
+        pub reaction: Reaction,
+    }
+
+    /// Only the author of a nickname can describe it, an empty description removes it
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct DescribeNickname {
+        pub target: ProfilID,
+        pub nickname: String,
+        pub description: String,
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct CommentNickname {
+        pub target: ProfilID,
+        pub nickname: String,
+        pub text: String,
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct DeleteComment {
+        pub target: ProfilID,
+        pub nickname: String,
+        /// id of the comment in the thread of the nickname
+        pub comment: u32,
+    }
+
+    /// Only the target can veto the nicknames proposed for them, `vetoed: false` lifts the veto
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct VetoNickname {
+        pub target: ProfilID,
+        pub nickname: String,
+        pub vetoed: bool,
     }
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -48,7 +111,7 @@ pub mod c2s {
     }
 }
 pub mod s2c {
-    use crate::{ClassID, ProfilID};
+    use crate::{ClassID, ProfilID, Reaction, VoteVisibility, VotingMode};
     use serde::{Deserialize, Serialize};
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -67,6 +130,26 @@ pub mod s2c {
         pub logged: bool,
         pub allowed_to_use_cmd: bool,
         pub classes: Vec<(ClassID, Class)>,
+        /// profile of the requester, as shown to the others
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub display_name: Option<String>,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub bio: Option<String>,
+        /// only set in the answer to the profile update which got refused
+        #[serde(default)]
+        pub refusal: Option<ProfileRefusal>,
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+    pub enum ProfileRefusal {
+        /// longer than `UpdateProfile::DISPLAY_NAME_MAX_LENGTH`
+        DisplayNameTooLong,
+        /// someone else already uses it, as a login or display name
+        DisplayNameTaken,
+        /// longer than `UpdateProfile::BIO_MAX_LENGTH`
+        BioTooLong,
     }
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -76,6 +159,76 @@ pub mod s2c {
         pub contain_you: bool,
         pub allowed_to_be_delete: bool,
         pub protected: bool,
+        /// how many votes or points the requester gave to this nickname
+        #[serde(default)]
+        pub your_votes: u32,
+        /// only filled when the votes are public
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub voters: Vec<String>,
+        /// discussion thread, from the oldest comment
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub comments: Vec<Comment>,
+        /// explanation given by the author
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub description: Option<String>,
+        #[serde(default)]
+        pub allowed_to_describe: bool,
+        /// refused by the target, only the target and the admins still see it
+        #[serde(default)]
+        pub vetoed: bool,
+        /// how many people reacted with each reaction, they don't count as votes
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub reactions: Vec<(Reaction, usize)>,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub your_reactions: Vec<Reaction>,
+    }
+
+    impl NicknameStatut {
+        /// in characters
+        pub const DESCRIPTION_MAX_LENGTH: usize = 200;
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct Comment {
+        pub id: u32,
+        pub author: String,
+        pub text: String,
+        /// seconds since the unix epoch
+        pub created_at: u64,
+        pub allowed_to_be_delete: bool,
+    }
+
+    impl Comment {
+        /// in characters
+        pub const MAX_LENGTH: usize = 280;
+        /// comments in the thread of a nickname
+        pub const MAX_PER_THREAD: usize = 100;
+    }
+
+    /// Why the server refused an action on a nickname
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+    pub enum Refusal {
+        NotAllowed,
+        SelfVote,
+        NoVotesLeft,
+        /// ballots are only accepted in the ranked mode
+        NotRanked,
+        /// no nickname can be proposed during a final
+        Frozen,
+        /// longer than `Comment::MAX_LENGTH`
+        CommentTooLong,
+        /// the thread already has `Comment::MAX_PER_THREAD` comments
+        ThreadFull,
+        /// longer than `NicknameStatut::DESCRIPTION_MAX_LENGTH`
+        DescriptionTooLong,
+        /// the target refused this nickname
+        Vetoed,
+        NoVetoesLeft,
     }
 
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -84,14 +237,72 @@ pub mod s2c {
         pub nicknames: Vec<NicknameStatut>,
         pub allowed_to_vote: bool,
         pub allowed_to_protect: bool,
+        #[serde(default)]
+        pub allowed_to_comment: bool,
+        #[serde(default)]
+        pub allowed_to_react: bool,
+        /// only set in the answer to the request which got refused
+        #[serde(default)]
+        pub refusal: Option<Refusal>,
+        #[serde(default)]
+        pub voting_mode: VotingMode,
+        /// 0 during the open proposal period, then increased by each final
+        #[serde(default)]
+        pub round: u32,
+        /// the voters are only sent when the votes are public
+        #[serde(default)]
+        pub visibility: VoteVisibility,
+        /// the counts are hidden until the results of the class are revealed
+        #[serde(default)]
+        pub blind: bool,
+        /// only sent to the target, when their classes allow vetoes
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub vetoes_left: Option<u32>,
+    }
+
+    /// Ballots of a target in the ranked mode and their tally
+    #[derive(Deserialize, Serialize, Debug, Clone, Default)]
+    pub struct RankedResults {
+        pub profil_id: ProfilID,
+        /// ballot of the requester, from the preferred nickname
+        pub your_ranking: Vec<String>,
+        pub ballots: usize,
+        /// first preferences of the nicknames still running, for each instant-runoff round
+        pub rounds: Vec<Vec<(String, u32)>>,
+        pub winner: Option<String>,
+        /// sorted from the best score
+        pub borda: Vec<(String, u32)>,
+        pub allowed_to_vote: bool,
+        /// only set in the answer to the request which got refused
+        #[serde(default)]
+        pub refusal: Option<Refusal>,
     }
 
     #[derive(Deserialize, Serialize, Debug, Clone, Default)]
     pub struct ProfilStats {
         pub profil_id: ProfilID,
+        #[serde(default)]
+        pub display_name: String,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub bio: Option<String>,
         pub total_votes: i32,
         pub total_propositions: i32,
         pub numbers_of_nickname: usize,
         pub numbers_of_classes: usize,
     }
+
+    /// Deployment overview, only sent to admins
+    #[derive(Deserialize, Serialize, Debug, Clone, Default)]
+    pub struct ServerStats {
+        pub total_profiles: usize,
+        pub total_classes: usize,
+        pub total_propositions: usize,
+        pub total_votes: usize,
+        /// profiles who voted during the last 24 hours
+        pub daily_active_voters: usize,
+        pub never_logged_in: usize,
+        pub lonely_people: Vec<String>,
+    }
 }
@@ -0,0 +1,161 @@
+use crate::data_server::DataServer;
+use common::ProfilID;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Common interface over the lock kinds we want to compare
+trait SharedServer: Sync {
+    fn read<R>(&self, f: impl FnOnce(&DataServer) -> R) -> R;
+    fn write<R>(&self, f: impl FnOnce(&mut DataServer) -> R) -> R;
+}
+
+impl SharedServer for Mutex<DataServer> {
+    fn read<R>(&self, f: impl FnOnce(&DataServer) -> R) -> R {
+        f(&self.lock().unwrap())
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut DataServer) -> R) -> R {
+        f(&mut self.lock().unwrap())
+    }
+}
+
+impl SharedServer for RwLock<DataServer> {
+    fn read<R>(&self, f: impl FnOnce(&DataServer) -> R) -> R {
+        f(&self.read().unwrap())
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut DataServer) -> R) -> R {
+        f(&mut self.write().unwrap())
+    }
+}
+
+/// Simple xorshift, we only need cheap and reproducible picks
+struct Picker(u64);
+
+impl Picker {
+    fn pick(&mut self, len: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % len as u64) as u32
+    }
+}
+
+const CLASSES: u32 = 20;
+const PROFILES_PER_CLASS: u32 = 30;
+const NICKNAMES_PER_PROFIL: u32 = 8;
+
+/// build a fake school, unrelated to the real data
+fn build_server() -> DataServer {
+    let mut server = DataServer::new(Default::default(), Default::default());
+    // refused votes are cheaper, they would skew the measure
+    server.set_allow_self_votes(true);
+    for class in 0..CLASSES {
+        // classes have to be created before their profiles, class ids are derived from the profil counter
+        let class_name = format!("class {class}");
+        server.add_class(class_name.clone()).unwrap();
+        for profil in 0..PROFILES_PER_CLASS {
+            let name = format!("profil {class}-{profil}");
+            server.add_profile(name.clone(), String::new()).unwrap();
+            let id = server.get_profil_id(&name).unwrap();
+            server.add_to_class(id, &class_name).unwrap();
+        }
+    }
+
+    let mut picker = Picker(0x2545F4914F6CDD1D);
+    for target in 1..=CLASSES * PROFILES_PER_CLASS {
+        for _ in 0..NICKNAMES_PER_PROFIL {
+            let voter = same_class_profil(target, &mut picker);
+            let nickname = format!("nickname {}", picker.pick(NICKNAMES_PER_PROFIL));
+            server
+                .vote(voter, ProfilID(target), nickname, None)
+                .unwrap();
+        }
+    }
+    server
+}
+
+/// pick someone in the same class as the target, ids are given in class order
+fn same_class_profil(target: u32, picker: &mut Picker) -> ProfilID {
+    let class = (target - 1) / PROFILES_PER_CLASS;
+    ProfilID(class * PROFILES_PER_CLASS + picker.pick(PROFILES_PER_CLASS) + 1)
+}
+
+/// return how many reads and writes were performed
+fn measure<S: SharedServer>(
+    server: &S,
+    readers: usize,
+    writers: usize,
+    duration: Duration,
+) -> (u64, u64) {
+    let running = AtomicBool::new(true);
+    let reads = AtomicU64::new(0);
+    let writes = AtomicU64::new(0);
+    let profiles = CLASSES * PROFILES_PER_CLASS;
+
+    thread::scope(|scope| {
+        for i in 0..readers {
+            let (running, reads) = (&running, &reads);
+            scope.spawn(move || {
+                let mut picker = Picker(i as u64 + 1);
+                let mut count = 0;
+                while running.load(Ordering::Relaxed) {
+                    let requester = ProfilID(picker.pick(profiles) + 1);
+                    let target = ProfilID(picker.pick(profiles) + 1);
+                    server.read(|server| server.nickname_list(Some(requester), target));
+                    count += 1;
+                }
+                reads.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+
+        for i in 0..writers {
+            let (running, writes) = (&running, &writes);
+            scope.spawn(move || {
+                let mut picker = Picker(i as u64 + 1000);
+                let mut count = 0;
+                while running.load(Ordering::Relaxed) {
+                    let target = picker.pick(profiles) + 1;
+                    let voter = same_class_profil(target, &mut picker);
+                    let nickname = format!("nickname {}", picker.pick(NICKNAMES_PER_PROFIL));
+                    server
+                        .write(|server| server.vote(voter, ProfilID(target), nickname, None))
+                        .unwrap();
+                    count += 1;
+                }
+                writes.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+
+        thread::sleep(duration);
+        running.store(false, Ordering::Relaxed);
+    });
+
+    (reads.into_inner(), writes.into_inner())
+}
+
+/// compare read throughput of a global mutex and a rwlock while votes are being cast
+pub fn run(readers: usize, writers: usize, duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+
+    let mutex = Mutex::new(build_server());
+    let (mutex_reads, mutex_writes) = measure(&mutex, readers, writers, duration);
+
+    let rwlock = RwLock::new(build_server());
+    let (rwlock_reads, rwlock_writes) = measure(&rwlock, readers, writers, duration);
+
+    format!(
+        "{readers} reader(s), {writers} writer(s), {} profiles, {seconds}s per run\n\
+         mutex:  {:.0} reads/s, {:.0} writes/s\n\
+         rwlock: {:.0} reads/s, {:.0} writes/s\n\
+         read speedup: x{:.2}",
+        CLASSES * PROFILES_PER_CLASS,
+        mutex_reads as f64 / seconds,
+        mutex_writes as f64 / seconds,
+        rwlock_reads as f64 / seconds,
+        rwlock_writes as f64 / seconds,
+        rwlock_reads as f64 / mutex_reads.max(1) as f64,
+    )
+}
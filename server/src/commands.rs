@@ -1,4 +1,6 @@
+use crate::data_server::api_tokens::TokenScope;
 use crate::data_server::permissions::InteractionPermission;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -66,6 +68,20 @@ pub struct RemoveFromClass {
     pub class_name: String,
 }
 
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Let someone manage the members and nicknames of a class")]
+pub struct AddClassAdmin {
+    pub profil_name: String,
+    pub class_name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Remove someone from the admins of a class")]
+pub struct RemoveClassAdmin {
+    pub profil_name: String,
+    pub class_name: String,
+}
+
 #[derive(Debug, StructOpt)]
 pub enum PermissionKind {
     Vote {
@@ -77,10 +93,21 @@ pub enum PermissionKind {
     Protect {
         permission: InteractionPermission,
     },
+    Comment {
+        permission: InteractionPermission,
+    },
+    /// classes used by the SpecificClasses permission
+    ScopeClasses {
+        classes: Vec<String>,
+    },
     UseCmd {
         #[structopt(parse(try_from_str))]
         permission: bool,
     },
+    /// give a role defined in the config, or remove it when none is given
+    Role {
+        role: Option<String>,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -90,3 +117,233 @@ pub struct ChangePermission {
     #[structopt(subcommand)]
     pub kind: PermissionKind,
 }
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List people who never logged in, or not for a while")]
+pub struct ViewInactive {
+    /// only look into this class
+    #[structopt(long)]
+    pub class: Option<String>,
+    /// also list people who did not log in during this many days
+    #[structopt(long)]
+    pub days: Option<u64>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Lift a login lockout on an account")]
+pub struct UnlockAccount {
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Compare read throughput of a mutex and a rwlock under concurrent votes")]
+pub struct Benchmark {
+    #[structopt(long, default_value = "4")]
+    pub readers: usize,
+    #[structopt(long, default_value = "1")]
+    pub writers: usize,
+    /// duration of each run
+    #[structopt(long, default_value = "3")]
+    pub seconds: u64,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List the active sessions of someone")]
+pub struct ListSessions {
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Log someone out of every device")]
+pub struct RevokeSessions {
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Create an api token for scripts, it is only shown once")]
+pub struct CreateToken {
+    pub name: String,
+    pub token_name: String,
+    #[structopt(long, possible_values = &TokenScope::variants(), case_insensitive = true, default_value = "Full")]
+    pub scope: TokenScope,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List the api tokens of someone")]
+pub struct ListTokens {
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Revoke an api token")]
+pub struct RevokeToken {
+    pub name: String,
+    pub token_name: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Change a permission of a template, the template is created if needed")]
+// otherwise clap refuses template names looking like a subcommand, such as "delegate"
+#[structopt(setting = AppSettings::InferSubcommands)]
+pub struct EditTemplate {
+    pub template: String,
+    #[structopt(subcommand)]
+    pub kind: PermissionKind,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Remove a permission template")]
+pub struct DeleteTemplate {
+    pub template: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Give the permissions of a template to every member of a class")]
+pub struct ApplyTemplate {
+    pub template: String,
+    #[structopt(long)]
+    pub class: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Set the template given to new members of a class, or remove it when none is given"
+)]
+pub struct SetClassTemplate {
+    pub class: String,
+    pub template: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Put a class in a group, or remove it from its group when none is given")]
+pub struct SetClassGroup {
+    pub class: String,
+    pub group: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Allow or forbid voting for yourself in a class, or follow the server rule when nothing is given"
+)]
+pub struct SetClassSelfVotes {
+    pub class: String,
+    #[structopt(parse(try_from_str))]
+    pub allowed: Option<bool>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List the votes people gave to themselves while it is forbidden")]
+pub struct ViewSelfVotes {
+    /// remove these votes
+    #[structopt(long)]
+    pub purge: bool,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Keep the most voted nicknames of each member of a class and reset the votes for a final round"
+)]
+pub struct StartFinal {
+    pub class: String,
+    /// how many nicknames go to the final
+    #[structopt(long, default_value = "3", validator = at_least_one)]
+    pub top: usize,
+}
+
+fn at_least_one(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("At least one nickname has to go to the final".to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Export the nicknames of a class and their votes as csv")]
+pub struct ExportResults {
+    pub class: String,
+    /// write in this file of the exports directory instead of the output, only a file name is accepted
+    #[structopt(long)]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "List the deleted nicknames which can still be restored")]
+pub struct ListTrash {
+    /// only list the nicknames of this person
+    #[structopt(long)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Restore the last deleted nickname of someone with this name, and its votes")]
+pub struct RestoreNickname {
+    pub name: String,
+    pub nickname: String,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "View the results of the previous rounds of someone")]
+pub struct ViewArchive {
+    pub name: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum VotingModeKind {
+    /// one vote per target
+    Single,
+    /// vote for up to max_votes nicknames per target
+    Approval { max_votes: u32 },
+    /// distribute points between the nicknames of a target
+    Points { points: u32 },
+    /// rank the nicknames of a target, tallied by instant-runoff
+    Ranked,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Hide the counts of a class to everyone but admins until its results are revealed"
+)]
+pub struct SetClassBlind {
+    pub class: String,
+    #[structopt(parse(try_from_str), default_value = "true")]
+    pub blind: bool,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Set how many nicknames the members of a class can veto on their own profil, 0 disables the vetoes"
+)]
+pub struct SetVetoBudget {
+    pub class: String,
+    pub budget: u32,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Show the counts of a blind class to everyone")]
+pub struct RevealResults {
+    pub class: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum VisibilityKind {
+    /// only the vote counts are shown
+    Counts,
+    /// the names of the voters are shown
+    Public,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Change what people can see of the votes of a class")]
+pub struct SetVoteVisibility {
+    pub class: String,
+    #[structopt(subcommand)]
+    pub visibility: VisibilityKind,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Change how votes are cast on the nicknames of a class")]
+pub struct SetVotingMode {
+    pub class: String,
+    #[structopt(subcommand)]
+    pub mode: VotingModeKind,
+}
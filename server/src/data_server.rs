@@ -1,26 +1,76 @@
+use crate::data_server::api_tokens::{ApiToken, TokenScope};
 use crate::data_server::mutation_tracker::MutationTracker;
-use crate::data_server::permissions::{InteractionPermission, Permissions};
+use crate::data_server::permissions::{InteractionPermission, Permissions, Templates};
+use crate::data_server::tally::Ballot;
 use crate::data_server::ServerError::{
-    ClassAlreadyExist, ClassDoesntExist, PersonAlreadyExist, PersonDoesntExist,
+    ClassAlreadyExist, ClassDoesntExist, FinalStarted, InvalidFileName, NicknameAlreadyExist,
+    NicknameDoesntExist, PersonAlreadyExist, PersonDoesntExist, TemplateDoesntExist,
+    TokenAlreadyExist, TokenDoesntExist, WriteFailed,
 };
-use common::packets::s2c;
-use common::{ClassID, Identity, ProfilID};
+use common::packets::s2c::{ProfileRefusal, Refusal};
+use common::packets::{c2s, s2c};
+use common::{ClassID, Identity, ProfilID, Reaction, VoteVisibility, VotingMode};
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::RandomState;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+pub mod api_tokens;
 pub mod mutation_tracker;
 pub mod permissions;
 pub mod serialization;
+pub mod tally;
 
 pub struct Profil {
     identity: Identity,
     permissions: Permissions,
     total_votes: i32,
     total_propositions: i32,
+    login_history: LoginHistory,
+    /// unix timestamp of the last vote
+    last_vote: Option<u64>,
+    api_tokens: Vec<ApiToken>,
+    /// name of the admin role, see `crate::roles`
+    role: Option<String>,
+    /// shown to the others instead of the login name
+    display_name: Option<String>,
+    bio: Option<String>,
+}
+
+impl Profil {
+    fn public_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.identity.name)
+    }
+}
+
+/// Login activity of a profil, persisted alongside its identity
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LoginHistory {
+    /// unix timestamp of the last successful login
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_login: Option<u64>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    pub login_count: u32,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    pub failed_login_count: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// current unix timestamp, in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 #[derive(Clone, Debug)]
@@ -29,6 +79,15 @@ pub enum ServerError {
     ClassDoesntExist,
     PersonAlreadyExist,
     ClassAlreadyExist,
+    TokenAlreadyExist,
+    TokenDoesntExist,
+    TemplateDoesntExist,
+    NicknameAlreadyExist,
+    NicknameDoesntExist,
+    FinalStarted,
+    InvalidFileName,
+    /// the path and the reason
+    WriteFailed(String),
 }
 
 impl Display for ServerError {
@@ -38,6 +97,14 @@ impl Display for ServerError {
             ClassDoesntExist => f.write_str("This class does not exist"),
             PersonAlreadyExist => f.write_str("This person already exists"),
             ClassAlreadyExist => f.write_str("This class already exists"),
+            TokenAlreadyExist => f.write_str("This token already exists"),
+            TokenDoesntExist => f.write_str("This token does not exist"),
+            TemplateDoesntExist => f.write_str("This template does not exist"),
+            NicknameAlreadyExist => f.write_str("This nickname already exists"),
+            NicknameDoesntExist => f.write_str("This nickname does not exist"),
+            FinalStarted => f.write_str("A final has started, its nicknames can't change anymore"),
+            InvalidFileName => f.write_str("This file name is not valid"),
+            WriteFailed(e) => write!(f, "Failed to write {e}"),
         }
     }
 }
@@ -48,6 +115,20 @@ impl Error for ServerError {}
 pub struct Class {
     name: String,
     profiles: HashSet<ProfilID>,
+    /// profils managing this class, they don't have to be part of it
+    admins: HashSet<ProfilID>,
+    /// classes sharing a group, like a whole grade, can be allowed to interact
+    group: Option<String>,
+    /// permission template given to new members
+    default_template: Option<String>,
+    /// override the server rule about voting for yourself
+    allow_self_votes: Option<bool>,
+    voting_mode: VotingMode,
+    visibility: VoteVisibility,
+    /// counts are hidden to everyone but admins until the results are revealed
+    blind: bool,
+    /// how many nicknames each member can veto on their own profil, 0 disables the vetoes
+    veto_budget: u32,
 }
 
 /// A single Nickname proposition
@@ -57,6 +138,104 @@ pub struct NickNameProposition {
     proposition: String,
     votes: Vec<ProfilID>,
     protected: bool,
+    /// position of this nickname in the ballot of each voter, in the ranked mode
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ranks: Vec<(ProfilID, u32)>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<Comment>,
+    /// id of the next comment, never reused after a deletion
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    next_comment_id: u32,
+    /// explanation given by the author
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// people who reacted, apart from the votes
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    reactions: HashMap<Reaction, Vec<ProfilID>>,
+    /// refused by the target, hidden to everyone but the admins and the target, its votes don't count
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    vetoed: bool,
+}
+
+impl NickNameProposition {
+    /// each voter once, in the points mode a voter is in the votes once per point
+    fn voters(&self) -> impl Iterator<Item = &ProfilID> {
+        self.votes
+            .iter()
+            .enumerate()
+            .filter(|(i, voter)| !self.votes[..*i].contains(voter))
+            .map(|(_, voter)| voter)
+    }
+
+    /// remove every trace of a deleted profil, apart from the authorship
+    fn forget(&mut self, removed: ProfilID) {
+        self.votes.retain(|voter| *voter != removed);
+        self.ranks.retain(|(voter, _)| *voter != removed);
+        self.comments.retain(|comment| comment.author != removed);
+        for reactors in self.reactions.values_mut() {
+            reactors.retain(|reactor| *reactor != removed);
+        }
+        self.reactions.retain(|_, reactors| !reactors.is_empty());
+    }
+}
+
+/// A nickname in the exported results of a class
+pub struct ResultLine {
+    pub target: String,
+    pub nickname: String,
+    pub votes: usize,
+    pub author: String,
+    pub description: String,
+    pub vetoed: bool,
+}
+
+/// A message in the discussion thread of a nickname
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Comment {
+    /// unique in its thread
+    id: u32,
+    author: ProfilID,
+    text: String,
+    created_at: u64,
+}
+
+/// Results of a finished round of a target, kept when a final starts
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedRound {
+    /// when the next round started
+    pub ended_at: u64,
+    /// nicknames and their vote count, from the most voted
+    pub results: Vec<(String, usize)>,
+}
+
+/// previous rounds of each target, the current round is the number of archived ones
+pub type Archives = HashMap<ProfilID, Vec<ArchivedRound>>;
+
+/// A deleted nickname, kept with its votes until it is restored or purged
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeletedNickname {
+    target: ProfilID,
+    proposition: NickNameProposition,
+    deleted_by: ProfilID,
+    deleted_at: u64,
+}
+
+/// deleted nicknames, from the oldest
+pub type Trash = Vec<DeletedNickname>;
+
+/// A deleted nickname in the listing of the trash
+pub struct TrashLine {
+    pub target: String,
+    pub nickname: String,
+    pub votes: usize,
+    pub deleted_by: String,
+    pub deleted_at: u64,
 }
 
 /// Global storage of most of the server content
@@ -67,6 +246,13 @@ pub struct DataServer {
     classes: MutationTracker<HashMap<ClassID, Class>>,
     free_class_id_beginning: u32,
     nick_name_proposition: MutationTracker<HashMap<ProfilID, Vec<NickNameProposition>>>,
+    templates: MutationTracker<Templates>,
+    archives: MutationTracker<Archives>,
+    trash: MutationTracker<Trash>,
+    /// server wide rule, classes can override it
+    allow_self_votes: bool,
+    /// how long deleted nicknames are kept, in seconds
+    trash_retention: u64,
 }
 
 impl DataServer {
@@ -100,6 +286,12 @@ impl DataServer {
             |serialization::Profil {
                  identity,
                  permissions,
+                 last_vote,
+                 login_history,
+                 api_tokens,
+                 role,
+                 display_name,
+                 bio,
              }| {
                 (
                     get_profil_id(identity.name.clone()),
@@ -108,6 +300,12 @@ impl DataServer {
                         permissions,
                         total_votes: 0,
                         total_propositions: 0,
+                        login_history,
+                        last_vote,
+                        api_tokens,
+                        role,
+                        display_name,
+                        bio,
                     },
                 )
             },
@@ -138,21 +336,43 @@ impl DataServer {
                 })
         };
 
-        let class_iter =
-            repartition
-                .classes
-                .into_iter()
-                .map(|serialization::Class { name, people }| {
-                    (
-                        get_class_id(&name),
-                        Class {
-                            name,
-                            profiles: HashSet::from_iter(people.iter().flat_map(|person_name| {
-                                raw_name_to_id_map.get(person_name).cloned()
-                            })),
-                        },
+        let class_iter = repartition.classes.into_iter().map(
+            |serialization::Class {
+                 name,
+                 people,
+                 admins,
+                 default_template,
+                 group,
+                 allow_self_votes,
+                 voting_mode,
+                 visibility,
+                 blind,
+                 veto_budget,
+             }| {
+                let to_ids = |names: Vec<String>| {
+                    HashSet::from_iter(
+                        names
+                            .iter()
+                            .flat_map(|person_name| raw_name_to_id_map.get(person_name).cloned()),
                     )
-                });
+                };
+                (
+                    get_class_id(&name),
+                    Class {
+                        name,
+                        profiles: to_ids(people),
+                        admins: to_ids(admins),
+                        default_template,
+                        group,
+                        allow_self_votes,
+                        voting_mode,
+                        visibility,
+                        blind,
+                        veto_budget,
+                    },
+                )
+            },
+        );
 
         let classes = HashMap::from_iter(class_iter);
 
@@ -166,6 +386,12 @@ impl DataServer {
             classes: MutationTracker::dirty(classes, last_class_id_used != last_class_id_used),
             free_class_id_beginning: last_class_id_used,
             nick_name_proposition: Default::default(),
+            // written on the first save so they can be edited by hand
+            templates: MutationTracker::dirty(permissions::default_templates(), true),
+            archives: Default::default(),
+            trash: Default::default(),
+            allow_self_votes: false,
+            trash_retention: 30 * 24 * 60 * 60,
         }
     }
 
@@ -198,7 +424,13 @@ impl DataServer {
             .values()
             .map(|profil| serialization::Profil {
                 identity: profil.identity.clone(),
-                permissions: profil.permissions,
+                permissions: profil.permissions.clone(),
+                last_vote: profil.last_vote,
+                login_history: profil.login_history,
+                api_tokens: profil.api_tokens.clone(),
+                role: profil.role.clone(),
+                display_name: profil.display_name.clone(),
+                bio: profil.bio.clone(),
             })
             .collect();
 
@@ -207,17 +439,28 @@ impl DataServer {
         let mut classes: Vec<_> = self
             .classes
             .values()
-            .map(|class| serialization::Class {
-                name: class.name.clone(),
-                people: class
-                    .profiles
-                    .iter()
-                    .flat_map(|id| {
-                        self.id_to_profil
-                            .get(id)
-                            .map(|profil| profil.identity.name.clone())
-                    })
-                    .collect(),
+            .map(|class| {
+                let to_names = |ids: &HashSet<ProfilID>| {
+                    ids.iter()
+                        .flat_map(|id| {
+                            self.id_to_profil
+                                .get(id)
+                                .map(|profil| profil.identity.name.clone())
+                        })
+                        .collect()
+                };
+                serialization::Class {
+                    name: class.name.clone(),
+                    people: to_names(&class.profiles),
+                    admins: to_names(&class.admins),
+                    default_template: class.default_template.clone(),
+                    group: class.group.clone(),
+                    allow_self_votes: class.allow_self_votes,
+                    voting_mode: class.voting_mode,
+                    visibility: class.visibility,
+                    blind: class.blind,
+                    veto_budget: class.veto_budget,
+                }
             })
             .collect();
 
@@ -236,7 +479,7 @@ impl DataServer {
                 if let Some(profil) = self.id_to_profil.get_mut(&proposition.author) {
                     profil.total_propositions += 1;
                 }
-                for voter in proposition.votes.iter() {
+                for voter in proposition.voters() {
                     if let Some(voter) = self.id_to_profil.get_mut(voter) {
                         voter.total_votes += 1;
                     };
@@ -254,6 +497,52 @@ impl DataServer {
         }
     }
 
+    /// everything will be written again on the next save, used when a save failed after the dirty flags were cleared
+    pub fn mark_unsaved(&mut self) {
+        self.id_to_profil.mark_dirty();
+        self.classes.mark_dirty();
+        self.nick_name_proposition.mark_dirty();
+        self.templates.mark_dirty();
+        self.archives.mark_dirty();
+        self.trash.mark_dirty();
+    }
+
+    pub fn load_templates(&mut self, templates: Templates) {
+        self.templates = MutationTracker::new(templates);
+    }
+
+    pub fn try_to_save_templates(&mut self) -> Option<Templates> {
+        if self.templates.clear_dirty() {
+            Some(self.templates.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn load_archives(&mut self, archives: Archives) {
+        self.archives = MutationTracker::new(archives);
+    }
+
+    pub fn try_to_save_archives(&mut self) -> Option<Archives> {
+        if self.archives.clear_dirty() {
+            Some(self.archives.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn load_trash(&mut self, trash: Trash) {
+        self.trash = MutationTracker::new(trash);
+    }
+
+    pub fn try_to_save_trash(&mut self) -> Option<Trash> {
+        if self.trash.clear_dirty() {
+            Some(self.trash.clone())
+        } else {
+            None
+        }
+    }
+
     pub fn try_to_save_profils(
         &mut self,
     ) -> Option<(serialization::PeopleRepartition, serialization::IdMap)> {
@@ -266,6 +555,9 @@ impl DataServer {
     }
 
     pub fn add_profile(&mut self, name: String, password: String) -> Result<(), ServerError> {
+        if self.is_name_taken(&name, None) {
+            return Err(PersonAlreadyExist);
+        }
         let entry = self.name_to_id.entry(name.clone());
         if let Occupied(_) = entry {
             return Err(PersonAlreadyExist);
@@ -282,6 +574,12 @@ impl DataServer {
                 permissions: Default::default(),
                 total_votes: 0,
                 total_propositions: 0,
+                login_history: Default::default(),
+                last_vote: None,
+                api_tokens: Vec::new(),
+                role: None,
+                display_name: None,
+                bio: None,
             },
         );
         Ok(())
@@ -298,14 +596,25 @@ impl DataServer {
         }
 
         self.nick_name_proposition.remove(&removed);
+        if self.archives.contains_key(&removed) {
+            self.archives.remove(&removed);
+        }
+        if self.trash.iter().any(|deleted| deleted.target == removed) {
+            self.trash.retain(|deleted| deleted.target != removed);
+        }
         for propositions in self.nick_name_proposition.values_mut() {
             for proposition in propositions {
-                proposition.votes.retain(|voter| voter != &removed);
+                proposition.forget(removed);
             }
         }
+        // the id can be given again, a restored nickname must not bring back the votes
+        for deleted in self.trash.iter_mut() {
+            deleted.proposition.forget(removed);
+        }
 
         for class in self.classes.values_mut() {
             class.profiles.remove(&removed);
+            class.admins.remove(&removed);
         }
 
         Ok(())
@@ -325,6 +634,14 @@ impl DataServer {
             Class {
                 name,
                 profiles: HashSet::new(),
+                admins: HashSet::new(),
+                default_template: None,
+                group: None,
+                allow_self_votes: None,
+                voting_mode: VotingMode::Single,
+                visibility: VoteVisibility::Counts,
+                blind: false,
+                veto_budget: 0,
             },
         );
         Ok(())
@@ -370,6 +687,39 @@ impl DataServer {
         people
     }
 
+    /// list every profil which never logged in, or not since `since`, optionally restricted to a class
+    pub fn find_inactive_profils(
+        &self,
+        class_name: Option<&str>,
+        since: Option<u64>,
+    ) -> Result<Vec<(String, LoginHistory)>, ServerError> {
+        let class = match class_name {
+            None => None,
+            Some(class_name) => Some(
+                self.classes
+                    .values()
+                    .find(|class| class.name == class_name)
+                    .ok_or(ClassDoesntExist)?,
+            ),
+        };
+
+        let mut people: Vec<_> = self
+            .id_to_profil
+            .iter()
+            .filter(|(id, _)| class.is_none_or(|class| class.profiles.contains(id)))
+            .filter(
+                |(_, profil)| match (profil.login_history.last_login, since) {
+                    (None, _) => true,
+                    (Some(last_login), Some(since)) => last_login < since,
+                    (Some(_), None) => false,
+                },
+            )
+            .map(|(_, profil)| (profil.identity.name.clone(), profil.login_history))
+            .collect();
+        people.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(people)
+    }
+
     pub fn get_password(&self, id: ProfilID) -> Result<String, ServerError> {
         let profil = self.id_to_profil.get(&id).ok_or(PersonDoesntExist)?;
         Ok(profil.identity.password.clone())
@@ -385,6 +735,56 @@ impl DataServer {
         Ok(())
     }
 
+    /// create a token for a profil, the token itself is returned and only its hash is kept
+    pub fn create_api_token(
+        &mut self,
+        id: ProfilID,
+        name: String,
+        scope: TokenScope,
+    ) -> Result<String, ServerError> {
+        let profil = self.id_to_profil.get_mut(&id).ok_or(PersonDoesntExist)?;
+        if profil.api_tokens.iter().any(|token| token.name == name) {
+            return Err(TokenAlreadyExist);
+        }
+
+        let (token, hash) = ApiToken::generate();
+        profil.api_tokens.push(ApiToken {
+            name,
+            hash,
+            scope,
+            created_at: now(),
+        });
+        Ok(token)
+    }
+
+    pub fn list_api_tokens(&self, id: ProfilID) -> Result<&[ApiToken], ServerError> {
+        let profil = self.id_to_profil.get(&id).ok_or(PersonDoesntExist)?;
+        Ok(&profil.api_tokens)
+    }
+
+    pub fn revoke_api_token(&mut self, id: ProfilID, name: &str) -> Result<(), ServerError> {
+        let profil = self.id_to_profil.get_mut(&id).ok_or(PersonDoesntExist)?;
+        let index = profil
+            .api_tokens
+            .iter()
+            .position(|token| token.name == name)
+            .ok_or(TokenDoesntExist)?;
+        profil.api_tokens.remove(index);
+        Ok(())
+    }
+
+    /// find who owns a token, and what it is allowed to do
+    pub fn authenticate_token(&self, token: &str) -> Option<(ProfilID, TokenScope)> {
+        let hash = ApiToken::hash(token);
+        self.id_to_profil.iter().find_map(|(id, profil)| {
+            profil
+                .api_tokens
+                .iter()
+                .find(|api_token| api_token.hash == hash)
+                .map(|api_token| (*id, api_token.scope))
+        })
+    }
+
     pub fn get_permissions_mut(&mut self, id: ProfilID) -> Result<&mut Permissions, ServerError> {
         self.id_to_profil
             .get_mut(&id)
@@ -392,8 +792,60 @@ impl DataServer {
             .ok_or(PersonDoesntExist)
     }
 
+    pub fn get_role(&self, id: ProfilID) -> Option<&str> {
+        self.id_to_profil.get(&id)?.role.as_deref()
+    }
+
+    pub fn set_role(&mut self, id: ProfilID, role: Option<String>) -> Result<(), ServerError> {
+        let profil = self.id_to_profil.get_mut(&id).ok_or(PersonDoesntExist)?;
+        profil.role = role;
+        Ok(())
+    }
+
+    /// A display name can't be taken from someone else, neither their login nor their display name
+    pub fn update_profile(
+        &mut self,
+        id: ProfilID,
+        display_name: String,
+        bio: String,
+    ) -> Result<(), ProfileRefusal> {
+        let display_name = display_name.trim();
+        let bio = bio.trim();
+        if display_name.chars().count() > c2s::UpdateProfile::DISPLAY_NAME_MAX_LENGTH {
+            return Err(ProfileRefusal::DisplayNameTooLong);
+        }
+        if bio.chars().count() > c2s::UpdateProfile::BIO_MAX_LENGTH {
+            return Err(ProfileRefusal::BioTooLong);
+        }
+        if self.is_name_taken(display_name, Some(id)) {
+            return Err(ProfileRefusal::DisplayNameTaken);
+        }
+
+        let Some(profil) = self.id_to_profil.get_mut(&id) else {
+            return Ok(());
+        };
+        profil.display_name = Some(display_name.to_string())
+            .filter(|name| !name.is_empty() && *name != profil.identity.name);
+        profil.bio = Some(bio.to_string()).filter(|bio| !bio.is_empty());
+        Ok(())
+    }
+
+    /// whether someone other than `except` uses this name, as a login or display name, ignoring the case
+    fn is_name_taken(&self, name: &str, except: Option<ProfilID>) -> bool {
+        let lowercase = name.to_lowercase();
+        self.id_to_profil.iter().any(|(id, profil)| {
+            Some(*id) != except
+                && (profil.identity.name.to_lowercase() == lowercase
+                    || profil.public_name().to_lowercase() == lowercase)
+        })
+    }
+
     pub fn change_name(&mut self, old_name: String, new_name: String) -> Result<(), ServerError> {
-        let id = self.name_to_id.remove(&old_name).ok_or(PersonDoesntExist)?;
+        let id = self.get_profil_id(&old_name)?;
+        if self.is_name_taken(&new_name, Some(id)) {
+            return Err(PersonAlreadyExist);
+        }
+        self.name_to_id.remove(&old_name);
         self.name_to_id.insert(new_name.clone(), id);
         let profil = self.id_to_profil.get_mut(&id).ok_or(PersonDoesntExist)?;
         profil.identity.name = new_name;
@@ -410,11 +862,91 @@ impl DataServer {
             .iter_mut()
             .find(|(_, class)| class.name == class_name)
             .ok_or(ClassDoesntExist)?;
-        if class.profiles.insert(profil_id) {
-            Ok(())
-        } else {
-            Err(PersonAlreadyExist)
+        if !class.profiles.insert(profil_id) {
+            return Err(PersonAlreadyExist);
+        }
+
+        let template = class
+            .default_template
+            .as_ref()
+            .and_then(|template| self.templates.get(template))
+            .cloned();
+        if let (Some(template), Some(profil)) = (template, self.id_to_profil.get_mut(&profil_id)) {
+            Self::apply_permissions(profil, template);
         }
+        Ok(())
+    }
+
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// get a template to edit it, it gets created with the default permissions if needed
+    pub fn template_mut(&mut self, name: String) -> &mut Permissions {
+        self.templates.entry(name).or_default()
+    }
+
+    pub fn delete_template(&mut self, name: &str) -> Result<(), ServerError> {
+        self.templates
+            .remove(name)
+            .map(|_| ())
+            .ok_or(TemplateDoesntExist)
+    }
+
+    /// give the permissions of a template to every member of a class, return how many profils were updated
+    pub fn apply_template(
+        &mut self,
+        template: &str,
+        class_name: &str,
+    ) -> Result<usize, ServerError> {
+        let template = self
+            .templates
+            .get(template)
+            .ok_or(TemplateDoesntExist)?
+            .clone();
+        let class = self
+            .classes
+            .values()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+
+        let mut count = 0;
+        for id in class.profiles.iter() {
+            if let Some(profil) = self.id_to_profil.get_mut(id) {
+                Self::apply_permissions(profil, template.clone());
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// template given to new members of a class, None to stop giving one
+    pub fn set_class_template(
+        &mut self,
+        class_name: &str,
+        template: Option<String>,
+    ) -> Result<(), ServerError> {
+        if template
+            .as_ref()
+            .is_some_and(|template| !self.templates.contains_key(template))
+        {
+            return Err(TemplateDoesntExist);
+        }
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.default_template = template;
+        Ok(())
+    }
+
+    /// the command permission is kept, templates are only about nicknames
+    fn apply_permissions(profil: &mut Profil, template: Permissions) {
+        profil.permissions = Permissions {
+            allowed_to_use_cmd: profil.permissions.allowed_to_use_cmd,
+            ..template
+        };
     }
 
     pub fn remove_from_class(
@@ -434,6 +966,58 @@ impl DataServer {
         }
     }
 
+    pub fn add_class_admin(
+        &mut self,
+        profil_id: ProfilID,
+        class_name: &str,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        // already being an admin is fine
+        class.admins.insert(profil_id);
+        Ok(())
+    }
+
+    pub fn remove_class_admin(
+        &mut self,
+        profil_id: ProfilID,
+        class_name: &str,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        if class.admins.remove(&profil_id) {
+            Ok(())
+        } else {
+            Err(PersonDoesntExist)
+        }
+    }
+
+    /// check if someone manages at least one class
+    pub fn is_class_admin(&self, admin: ProfilID) -> bool {
+        self.classes
+            .values()
+            .any(|class| class.admins.contains(&admin))
+    }
+
+    pub fn is_admin_of_class(&self, admin: ProfilID, class_name: &str) -> bool {
+        self.classes
+            .values()
+            .any(|class| class.name == class_name && class.admins.contains(&admin))
+    }
+
+    /// check if the target is in a class managed by the admin
+    pub fn is_class_admin_of(&self, admin: ProfilID, target: ProfilID) -> bool {
+        self.classes
+            .values()
+            .any(|class| class.admins.contains(&admin) && class.profiles.contains(&target))
+    }
+
     /// check if two profils share the same class
     pub fn are_in_same_class(&self, a: ProfilID, b: ProfilID) -> bool {
         for (_, class) in self.classes.iter() {
@@ -444,9 +1028,164 @@ impl DataServer {
         false
     }
 
+    pub fn is_in_class(&self, profil: ProfilID, class_name: &str) -> bool {
+        self.classes
+            .values()
+            .any(|class| class.name == class_name && class.profiles.contains(&profil))
+    }
+
+    pub fn class_members(&self, class_name: &str) -> Vec<ProfilID> {
+        self.classes
+            .values()
+            .filter(|class| class.name == class_name)
+            .flat_map(|class| class.profiles.iter().copied())
+            .collect()
+    }
+
+    /// everyone sharing a class with the profil, the profil included
+    pub fn classmates_of(&self, profil: ProfilID) -> HashSet<ProfilID> {
+        self.classes
+            .values()
+            .filter(|class| class.profiles.contains(&profil))
+            .flat_map(|class| class.profiles.iter().copied())
+            .chain(std::iter::once(profil))
+            .collect()
+    }
+
+    pub fn is_in_any_class(&self, profil: ProfilID) -> bool {
+        self.classes
+            .values()
+            .any(|class| class.profiles.contains(&profil))
+    }
+
+    /// check if two profils share a class, or are in classes of the same group
+    pub fn are_in_same_class_group(&self, a: ProfilID, b: ProfilID) -> bool {
+        let groups_of = |profil: ProfilID| {
+            self.classes
+                .values()
+                .filter(move |class| class.profiles.contains(&profil))
+                .flat_map(|class| class.group.as_ref())
+        };
+        self.are_in_same_class(a, b) || groups_of(a).any(|group| groups_of(b).any(|g| g == group))
+    }
+
+    pub fn set_allow_self_votes(&mut self, allowed: bool) {
+        self.allow_self_votes = allowed;
+    }
+
+    pub fn set_trash_retention(&mut self, retention: Duration) {
+        self.trash_retention = retention.as_secs();
+    }
+
+    /// None to follow the server rule
+    pub fn set_class_allow_self_votes(
+        &mut self,
+        class_name: &str,
+        allowed: Option<bool>,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.allow_self_votes = allowed;
+        Ok(())
+    }
+
+    /// a class forbidding it wins over one allowing it, and both over the server rule
+    pub fn are_self_votes_allowed(&self, profil: ProfilID) -> bool {
+        let mut allowed = None;
+        for class in self.classes.values() {
+            if class.profiles.contains(&profil) {
+                match class.allow_self_votes {
+                    Some(false) => return false,
+                    Some(true) => allowed = Some(true),
+                    None => {}
+                }
+            }
+        }
+        allowed.unwrap_or(self.allow_self_votes)
+    }
+
+    /// list the nicknames people voted for themselves while it is forbidden
+    pub fn find_self_votes(&self) -> Vec<(ProfilID, String)> {
+        let mut self_votes: Vec<_> = self
+            .nick_name_proposition
+            .iter()
+            .filter(|(target, _)| !self.are_self_votes_allowed(**target))
+            .flat_map(|(target, propositions)| {
+                propositions
+                    .iter()
+                    .filter(|proposition| proposition.votes.contains(target))
+                    .map(|proposition| (*target, proposition.proposition.clone()))
+            })
+            .collect();
+        self_votes.sort_by(|(a, x), (b, y)| a.0.cmp(&b.0).then(x.cmp(y)));
+        self_votes
+    }
+
+    /// remove the votes found by `find_self_votes`, nicknames left without votes are removed if they were self-proposed
+    pub fn purge_self_votes(&mut self) -> usize {
+        let targets: Vec<_> = self
+            .nick_name_proposition
+            .keys()
+            .filter(|target| !self.are_self_votes_allowed(**target))
+            .copied()
+            .collect();
+
+        let mut purged = 0;
+        for target in targets {
+            let Some(propositions) = self.nick_name_proposition.get_mut(&target) else {
+                continue;
+            };
+            let mut delta_votes = 0;
+            let mut delta_propositions = 0;
+            propositions.retain_mut(|proposition| {
+                proposition.ranks.retain(|(voter, _)| *voter != target);
+                let before = proposition.votes.len();
+                proposition.votes.retain(|voter| *voter != target);
+                if proposition.votes.len() == before {
+                    return true;
+                }
+                purged += 1;
+                delta_votes += 1;
+                if proposition.author == target && proposition.votes.is_empty() {
+                    delta_propositions += 1;
+                    return false;
+                }
+                true
+            });
+
+            if delta_votes > 0 {
+                let profil = self.id_to_profil.get_mut(&target);
+                if let Some(profil) = profil {
+                    profil.total_votes -= delta_votes;
+                    profil.total_propositions -= delta_propositions;
+                }
+            }
+        }
+        purged
+    }
+
+    pub fn set_class_group(
+        &mut self,
+        class_name: &str,
+        group: Option<String>,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.group = group;
+        Ok(())
+    }
+
+    /// `scope_classes` is only used by `InteractionPermission::SpecificClasses`
     pub fn is_action_allowed_between(
         &self,
         interaction_permission: InteractionPermission,
+        scope_classes: &[String],
         editor: ProfilID,
         target: ProfilID,
     ) -> bool {
@@ -454,69 +1193,522 @@ impl DataServer {
             InteractionPermission::Forbidden => false,
             InteractionPermission::YourSelf => editor == target,
             InteractionPermission::SameClass => self.are_in_same_class(editor, target),
+            InteractionPermission::OthersInSameClass => {
+                editor != target && self.are_in_same_class(editor, target)
+            }
+            InteractionPermission::SpecificClasses => self.classes.values().any(|class| {
+                class.profiles.contains(&target) && scope_classes.contains(&class.name)
+            }),
+            InteractionPermission::SameClassGroup => self.are_in_same_class_group(editor, target),
             InteractionPermission::AnyBody => true,
         }
     }
 
-    pub fn get_permission(&self, profil_id: ProfilID) -> Option<Permissions> {
+    pub fn get_permission(&self, profil_id: ProfilID) -> Option<&Permissions> {
         self.id_to_profil
             .get(&profil_id)
-            .map(|profil| profil.permissions)
+            .map(|profil| &profil.permissions)
     }
 
     /// voting and adding a nickname is the same operation, if the voter or target doesn't exist, it simply does nothing
-    pub fn vote(&mut self, voter: ProfilID, target: ProfilID, proposition: String) {
-        let Some(permissions) = self.get_permission(voter) else {
-            return;
-        };
-        if !self.is_action_allowed_between(permissions.vote, voter, target) {
-            return;
-        };
+    /// `points` is only used by the points voting mode
+    pub fn vote(
+        &mut self,
+        voter: ProfilID,
+        target: ProfilID,
+        proposition: String,
+        points: Option<u32>,
+    ) -> Result<(), Refusal> {
+        self.check_vote(voter, target)?;
 
         let proposition = proposition.trim().to_string();
         if proposition.is_empty() {
-            return;
+            return Ok(());
         };
+        let voting_mode = self.voting_mode_of(target);
+        let in_final = self.round_of(target) > 0;
         let nicknames = match self.nick_name_proposition.entry(target) {
             Occupied(entry) => entry.into_mut(),
             Vacant(entry) if self.id_to_profil.contains_key(&target) => entry.insert(vec![]),
-            _ => return,
+            _ => return Ok(()),
+        };
+
+        let index = nicknames.iter().position(|n| n.proposition == proposition);
+        if index.is_some_and(|i| nicknames[i].vetoed) {
+            return Err(Refusal::Vetoed);
+        }
+        // the final ballot is frozen
+        if index.is_none() && in_final {
+            return Err(Refusal::Frozen);
+        }
+        let count = |votes: &Vec<ProfilID>| votes.iter().filter(|p| **p == voter).count() as u32;
+        // the total counts the nicknames voted for, not the points
+        let voted = |votes: &Vec<ProfilID>| votes.contains(&voter) as i32;
+        let current = index.map_or(0, |i| count(&nicknames[i].votes));
+        let used: u32 = nicknames.iter().map(|n| count(&n.votes)).sum();
+
+        // how many votes the voter gives to the proposition once done
+        let (allocation, move_vote) = match voting_mode {
+            VotingMode::Single => (1, true),
+            VotingMode::Approval { .. } if current > 0 => (0, false),
+            VotingMode::Approval { max_votes } if used >= max_votes => {
+                return Err(Refusal::NoVotesLeft)
+            }
+            VotingMode::Approval { .. } => (1, false),
+            // votes are replaced by ballots, voting only proposes the nickname
+            VotingMode::Ranked => (0, false),
+            VotingMode::Points { points: budget } => {
+                let wanted = points.unwrap_or(1);
+                // the points come from the client, they can be anything
+                if wanted > budget.saturating_sub(used - current) {
+                    return Err(Refusal::NoVotesLeft);
+                }
+                (wanted, false)
+            }
         };
 
         let mut delta_votes = 0;
         let mut delta_propositions = 0;
 
-        let mut found = false; // we don't use return here because we **need** to cover all nicknames
-        for nickname in nicknames.iter_mut() {
-            nickname.votes.retain(|p| {
-                let keep = *p != voter;
-                if !keep {
-                    delta_votes -= 1;
-                };
-                keep
-            });
-            if nickname.proposition == proposition {
-                found = true;
-                nickname.votes.push(voter);
-                delta_votes += 1;
+        if move_vote {
+            for nickname in nicknames.iter_mut() {
+                delta_votes -= voted(&nickname.votes);
+                nickname.votes.retain(|p| *p != voter);
             }
         }
-        if !found {
-            delta_propositions += 1;
-            delta_votes += 1;
-            nicknames.push(NickNameProposition {
-                author: voter,
-                proposition,
-                votes: vec![voter],
-                protected: false,
-            })
-        }
-        let _ = nicknames;
+
+        let i = match index {
+            Some(i) => i,
+            None if allocation == 0 && voting_mode != VotingMode::Ranked => return Ok(()),
+            None => {
+                delta_propositions += 1;
+                nicknames.push(NickNameProposition {
+                    author: voter,
+                    proposition,
+                    votes: vec![],
+                    protected: false,
+                    ranks: vec![],
+                    comments: vec![],
+                    next_comment_id: 0,
+                    description: None,
+                    reactions: HashMap::new(),
+                    vetoed: false,
+                });
+                nicknames.len() - 1
+            }
+        };
+        let votes = &mut nicknames[i].votes;
+        delta_votes -= voted(votes);
+        votes.retain(|p| *p != voter);
+        votes.extend(std::iter::repeat_n(voter, allocation as usize));
+        delta_votes += voted(votes);
 
         // we know that the profil exist since we already checked its permissions
         let voter = self.id_to_profil.get_mut(&voter).unwrap();
         voter.total_propositions += delta_propositions;
         voter.total_votes += delta_votes;
+        voter.last_vote = Some(now());
+        Ok(())
+    }
+
+    /// refuse the vote of `voter` on the nicknames of `target` if the rules forbid it
+    fn check_vote(&self, voter: ProfilID, target: ProfilID) -> Result<(), Refusal> {
+        let Some(permissions) = self.get_permission(voter) else {
+            return Err(Refusal::NotAllowed);
+        };
+        if !self.is_action_allowed_between(
+            permissions.vote,
+            &permissions.scope_classes,
+            voter,
+            target,
+        ) {
+            return Err(Refusal::NotAllowed);
+        };
+        if voter == target && !self.are_self_votes_allowed(target) {
+            return Err(Refusal::SelfVote);
+        }
+        Ok(())
+    }
+
+    /// replace the ballot of `voter`, unknown and repeated nicknames are ignored, an empty ranking withdraws the ballot
+    pub fn rank(
+        &mut self,
+        voter: ProfilID,
+        target: ProfilID,
+        ranking: Vec<String>,
+    ) -> Result<(), Refusal> {
+        self.check_vote(voter, target)?;
+        if self.voting_mode_of(target) != VotingMode::Ranked {
+            return Err(Refusal::NotRanked);
+        }
+        let Some(nicknames) = self.nick_name_proposition.get_mut(&target) else {
+            return Ok(());
+        };
+
+        for nickname in nicknames.iter_mut() {
+            nickname.ranks.retain(|(p, _)| *p != voter);
+        }
+        let mut rank = 0;
+        for proposition in ranking {
+            let proposition = proposition.trim();
+            let Some(nickname) = nicknames.iter_mut().find(|n| n.proposition == proposition) else {
+                continue;
+            };
+            if nickname.ranks.iter().any(|(p, _)| *p == voter) {
+                continue;
+            }
+            nickname.ranks.push((voter, rank));
+            rank += 1;
+        }
+
+        // we know that the profil exist since we already checked its permissions
+        self.id_to_profil.get_mut(&voter).unwrap().last_vote = Some(now());
+        Ok(())
+    }
+
+    /// ballots of a target as indexes in its nickname list
+    fn ballots<'a>(
+        propositions: impl IntoIterator<Item = &'a NickNameProposition>,
+    ) -> HashMap<ProfilID, Ballot> {
+        let mut ranked: HashMap<ProfilID, Vec<(u32, usize)>> = HashMap::new();
+        for (index, proposition) in propositions.into_iter().enumerate() {
+            if proposition.vetoed {
+                continue;
+            }
+            for (voter, rank) in proposition.ranks.iter() {
+                ranked.entry(*voter).or_default().push((*rank, index));
+            }
+        }
+        ranked
+            .into_iter()
+            .map(|(voter, mut ballot)| {
+                ballot.sort();
+                (voter, ballot.into_iter().map(|(_, index)| index).collect())
+            })
+            .collect()
+    }
+
+    /// counted votes of each nickname of a target, in the ranked mode they are the first preferences
+    fn counted_votes(&self, target: ProfilID) -> Vec<Vec<ProfilID>> {
+        let Some(propositions) = self.nick_name_proposition.get(&target) else {
+            return vec![];
+        };
+        let mut votes: Vec<_> = propositions
+            .iter()
+            .map(|p| match p.vetoed {
+                true => vec![],
+                false => p.votes.clone(),
+            })
+            .collect();
+        if self.voting_mode_of(target) == VotingMode::Ranked {
+            for (voter, ballot) in Self::ballots(propositions) {
+                if let Some(first) = ballot.first() {
+                    votes[*first].push(voter);
+                }
+            }
+        }
+        votes
+    }
+
+    fn counts(&self, target: ProfilID) -> Vec<usize> {
+        self.counted_votes(target).iter().map(Vec::len).collect()
+    }
+
+    /// 0 during the open proposal period, then increased by each final
+    pub fn round_of(&self, target: ProfilID) -> usize {
+        self.archives.get(&target).map_or(0, Vec::len)
+    }
+
+    pub fn archives_of(&self, target: ProfilID) -> &[ArchivedRound] {
+        self.archives.get(&target).map_or(&[], Vec::as_slice)
+    }
+
+    /// archive the results of every member of a class, keep their `top` most voted nicknames and reset the votes
+    pub fn start_final(&mut self, class_name: &str, top: usize) -> Result<usize, ServerError> {
+        let class = self
+            .classes
+            .values()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        let targets: Vec<_> = class.profiles.iter().copied().collect();
+
+        let mut started = 0;
+        for target in targets {
+            // members without nicknames move to the final too, so the whole class stays in the same round
+            let results = self.archive_propositions(target, top);
+            self.archives
+                .entry(target)
+                .or_default()
+                .push(ArchivedRound {
+                    ended_at: now(),
+                    results,
+                });
+            started += 1;
+        }
+        Ok(started)
+    }
+
+    /// keep the `top` most voted nicknames of a target without their votes, and return the results to archive
+    fn archive_propositions(&mut self, target: ProfilID, top: usize) -> Vec<(String, usize)> {
+        let counts = self.counts(target);
+        let Some(propositions) = self.nick_name_proposition.get_mut(&target) else {
+            return vec![];
+        };
+
+        // stable sort, older nicknames win the ties
+        let mut ranked: Vec<_> = std::mem::take(propositions)
+            .into_iter()
+            .zip(counts)
+            .collect();
+        ranked.sort_by_key(|(proposition, count)| (proposition.vetoed, std::cmp::Reverse(*count)));
+        let results = ranked
+            .iter()
+            .map(|(proposition, count)| (proposition.proposition.clone(), *count))
+            .collect();
+
+        for (rank, (mut proposition, _)) in ranked.into_iter().enumerate() {
+            for voter in proposition.voters() {
+                if let Some(voter) = self.id_to_profil.get_mut(voter) {
+                    voter.total_votes -= 1;
+                }
+            }
+            proposition.votes.clear();
+            proposition.ranks.clear();
+            if rank < top && !proposition.vetoed {
+                propositions.push(proposition);
+            } else if let Some(author) = self.id_to_profil.get_mut(&proposition.author) {
+                author.total_propositions -= 1;
+            }
+        }
+        results
+    }
+
+    /// build a packet with the tally of the ballots of a target
+    pub fn ranked_results(
+        &self,
+        requester: Option<ProfilID>,
+        asked_profil: ProfilID,
+    ) -> s2c::RankedResults {
+        // vetoed nicknames are hidden from the candidates too
+        let sees_vetoed = self.sees_vetoed(requester, asked_profil);
+        let propositions: Vec<_> = self
+            .nick_name_proposition
+            .get(&asked_profil)
+            .into_iter()
+            .flatten()
+            .filter(|proposition| !proposition.vetoed || sees_vetoed)
+            .collect();
+        let name = |index: usize| propositions[index].proposition.clone();
+
+        let ballots = Self::ballots(propositions.iter().copied());
+        let your_ranking = requester
+            .and_then(|requester| ballots.get(&requester))
+            .map(|ballot| ballot.iter().copied().map(name).collect())
+            .unwrap_or_default();
+        let ballots: Vec<Ballot> = ballots.into_values().collect();
+
+        let runoff = tally::instant_runoff(propositions.len(), &ballots);
+        let mut borda: Vec<_> = tally::borda(propositions.len(), &ballots)
+            .into_iter()
+            .enumerate()
+            .map(|(index, score)| (name(index), score))
+            .collect();
+        borda.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let mut results = s2c::RankedResults {
+            profil_id: asked_profil,
+            your_ranking,
+            ballots: ballots.len(),
+            rounds: runoff
+                .rounds
+                .into_iter()
+                .map(|round| {
+                    round
+                        .into_iter()
+                        .map(|(index, count)| (name(index), count))
+                        .collect()
+                })
+                .collect(),
+            winner: runoff.winner.map(name),
+            borda,
+            allowed_to_vote: requester.is_some_and(|r| self.check_vote(r, asked_profil).is_ok()),
+            refusal: None,
+        };
+        // the tally would reveal the hidden counts
+        if self.is_blind_for(requester, asked_profil) {
+            results.rounds.clear();
+            results.winner = None;
+            results.borda.clear();
+        }
+        results
+    }
+
+    /// names of the voters, once each since a voter can give several points
+    fn voter_names(&self, votes: &[ProfilID]) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for voter in votes {
+            if let Some(profil) = self.id_to_profil.get(voter) {
+                let name = profil.public_name();
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// the mode of the target's class, when several classes set one the oldest class wins
+    pub fn voting_mode_of(&self, target: ProfilID) -> VotingMode {
+        self.classes
+            .iter()
+            .filter(|(_, class)| {
+                class.profiles.contains(&target) && class.voting_mode != VotingMode::Single
+            })
+            .min_by_key(|(id, _)| id.0)
+            .map(|(_, class)| class.voting_mode)
+            .unwrap_or_default()
+    }
+
+    /// when the target's classes disagree, the most private visibility wins
+    pub fn visibility_of(&self, target: ProfilID) -> VoteVisibility {
+        self.classes
+            .values()
+            .filter(|class| class.profiles.contains(&target))
+            .map(|class| class.visibility)
+            .min()
+            .unwrap_or_default()
+    }
+
+    pub fn set_visibility(
+        &mut self,
+        class_name: &str,
+        visibility: VoteVisibility,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.visibility = visibility;
+        Ok(())
+    }
+
+    /// admins, the admins of the target's classes and anyone with a role
+    fn is_moderator_of(&self, requester: ProfilID, target: ProfilID) -> bool {
+        self.is_class_admin_of(requester, target)
+            || self.get_role(requester).is_some()
+            || self
+                .get_permission(requester)
+                .is_some_and(|p| p.allowed_to_use_cmd)
+    }
+
+    /// the counts of a blind class are only seen by the moderators of the target
+    fn is_blind_for(&self, requester: Option<ProfilID>, target: ProfilID) -> bool {
+        let blind = self
+            .classes
+            .values()
+            .any(|class| class.blind && class.profiles.contains(&target));
+        blind && !requester.is_some_and(|requester| self.is_moderator_of(requester, target))
+    }
+
+    /// vetoed nicknames are kept for the target and their moderators
+    fn sees_vetoed(&self, requester: Option<ProfilID>, target: ProfilID) -> bool {
+        requester
+            .is_some_and(|requester| requester == target || self.is_moderator_of(requester, target))
+    }
+
+    /// when the target's classes disagree, the largest budget wins
+    pub fn veto_budget_of(&self, target: ProfilID) -> u32 {
+        self.classes
+            .values()
+            .filter(|class| class.profiles.contains(&target))
+            .map(|class| class.veto_budget)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn set_veto_budget(&mut self, class_name: &str, budget: u32) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.veto_budget = budget;
+        Ok(())
+    }
+
+    fn vetoes_used(&self, target: ProfilID) -> u32 {
+        self.nick_name_proposition
+            .get(&target)
+            .map_or(0, |nicknames| {
+                nicknames.iter().filter(|n| n.vetoed).count() as u32
+            })
+    }
+
+    /// Veto a nickname proposed for yourself, or lift the veto to get it back in the budget.
+    /// Protected nicknames can't be vetoed, the votes of a vetoed nickname are released
+    pub fn veto(
+        &mut self,
+        requester: ProfilID,
+        target: ProfilID,
+        nickname: String,
+        vetoed: bool,
+    ) -> Result<(), Refusal> {
+        let budget = self.veto_budget_of(target);
+        if requester != target || (vetoed && budget == 0) {
+            return Err(Refusal::NotAllowed);
+        }
+        let used = self.vetoes_used(target);
+        let Some(proposition) = self
+            .nick_name_proposition
+            .get_mut(&target)
+            .and_then(|nicknames| nicknames.iter_mut().find(|n| n.proposition == nickname))
+        else {
+            return Ok(());
+        };
+        if vetoed && !proposition.vetoed {
+            if proposition.protected {
+                return Err(Refusal::NotAllowed);
+            }
+            if used >= budget {
+                return Err(Refusal::NoVetoesLeft);
+            }
+            // the voters can't see it anymore, they get their votes back to use them elsewhere
+            for voter in proposition.voters() {
+                if let Some(voter) = self.id_to_profil.get_mut(voter) {
+                    voter.total_votes -= 1;
+                }
+            }
+            proposition.votes.clear();
+            proposition.ranks.clear();
+        }
+        proposition.vetoed = vetoed;
+        Ok(())
+    }
+
+    /// false reveals the results
+    pub fn set_class_blind(&mut self, class_name: &str, blind: bool) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.blind = blind;
+        Ok(())
+    }
+
+    pub fn set_voting_mode(
+        &mut self,
+        class_name: &str,
+        voting_mode: VotingMode,
+    ) -> Result<(), ServerError> {
+        let class = self
+            .classes
+            .values_mut()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        class.voting_mode = voting_mode;
+        Ok(())
     }
 
     /// Attempt to perform a delete operation
@@ -524,11 +1716,26 @@ impl DataServer {
         let Some(permissions) = self.get_permission(deleter) else {
             return;
         };
-        let is_allowed_to_delete =
-            self.is_action_allowed_between(permissions.delete, deleter, target);
-        let can_by_pass_protect =
-            self.is_action_allowed_between(permissions.protect_nickname, deleter, target);
-
+        let is_class_admin = self.is_class_admin_of(deleter, target);
+        let is_allowed_to_delete = is_class_admin
+            || self.is_action_allowed_between(
+                permissions.delete,
+                &permissions.scope_classes,
+                deleter,
+                target,
+            );
+        let can_by_pass_protect = is_class_admin
+            || self.is_action_allowed_between(
+                permissions.protect_nickname,
+                &permissions.scope_classes,
+                deleter,
+                target,
+            );
+
+        // the final ballot is frozen
+        if self.round_of(target) > 0 {
+            return;
+        }
         let Some(nicknames) = self.nick_name_proposition.get_mut(&target) else {
             return;
         };
@@ -543,12 +1750,301 @@ impl DataServer {
             if let Some(profil) = self.id_to_profil.get_mut(&proposition.author) {
                 profil.total_propositions -= 1;
             }
-            for voter in proposition.votes.iter() {
+            for voter in proposition.voters() {
                 if let Some(voter) = self.id_to_profil.get_mut(voter) {
                     voter.total_votes -= 1;
                 };
             }
+            self.trash.push(DeletedNickname {
+                target,
+                proposition,
+                deleted_by: deleter,
+                deleted_at: now(),
+            });
+        }
+    }
+
+    /// deleted nicknames of a target, or of everyone, from the oldest
+    pub fn list_trash(&self, target: Option<ProfilID>) -> Vec<TrashLine> {
+        let name = |id: &ProfilID| {
+            self.id_to_profil
+                .get(id)
+                .map(|profil| profil.identity.name.clone())
+                .unwrap_or_default()
+        };
+        self.trash
+            .iter()
+            .filter(|deleted| target.is_none_or(|target| deleted.target == target))
+            .map(|deleted| TrashLine {
+                target: name(&deleted.target),
+                nickname: deleted.proposition.proposition.clone(),
+                votes: deleted.proposition.voters().count(),
+                deleted_by: name(&deleted.deleted_by),
+                deleted_at: deleted.deleted_at,
+            })
+            .collect()
+    }
+
+    /// Put back the last deleted nickname of a target with this name.
+    /// Votes exceeding what the voting mode allows, because they were given elsewhere since, are dropped
+    pub fn restore_nickname(
+        &mut self,
+        target: ProfilID,
+        nickname: &str,
+    ) -> Result<(), ServerError> {
+        // the final ballot is frozen
+        if self.round_of(target) > 0 {
+            return Err(FinalStarted);
+        }
+        if self
+            .nick_name_proposition
+            .get(&target)
+            .is_some_and(|nicknames| nicknames.iter().any(|n| n.proposition == nickname))
+        {
+            return Err(NicknameAlreadyExist);
+        }
+        let index = self
+            .trash
+            .iter()
+            .rposition(|deleted| {
+                deleted.target == target && deleted.proposition.proposition == nickname
+            })
+            .ok_or(NicknameDoesntExist)?;
+        let DeletedNickname {
+            mut proposition, ..
+        } = self.trash.remove(index);
+
+        let budget = match self.voting_mode_of(target) {
+            VotingMode::Single => 1,
+            VotingMode::Approval { max_votes } => max_votes,
+            VotingMode::Points { points } => points,
+            VotingMode::Ranked => u32::MAX,
+        };
+        let nicknames = self.nick_name_proposition.entry(target).or_default();
+        let mut used: HashMap<ProfilID, u32> = HashMap::new();
+        for voter in nicknames.iter().flat_map(|n| n.votes.iter()) {
+            *used.entry(*voter).or_default() += 1;
+        }
+        proposition.votes.retain(|voter| {
+            let used = used.entry(*voter).or_default();
+            *used += 1;
+            *used <= budget
+        });
+
+        if let Some(author) = self.id_to_profil.get_mut(&proposition.author) {
+            author.total_propositions += 1;
+        }
+        for voter in proposition.voters() {
+            if let Some(voter) = self.id_to_profil.get_mut(voter) {
+                voter.total_votes += 1;
+            }
         }
+        nicknames.push(proposition);
+        Ok(())
+    }
+
+    /// definitely remove the nicknames deleted before the retention period
+    pub fn purge_trash(&mut self) -> usize {
+        let limit = now().saturating_sub(self.trash_retention);
+        let expired = self
+            .trash
+            .iter()
+            .filter(|deleted| deleted.deleted_at < limit)
+            .count();
+        // avoid marking the trash as modified for nothing
+        if expired > 0 {
+            self.trash.retain(|deleted| deleted.deleted_at >= limit);
+        }
+        expired
+    }
+
+    /// Toggle a reaction on a nickname proposition, the people allowed to vote for the target can react
+    pub fn react(
+        &mut self,
+        reactor: ProfilID,
+        target: ProfilID,
+        nickname: String,
+        reaction: Reaction,
+    ) -> Result<(), Refusal> {
+        if !self.is_allowed_to_react(reactor, target) {
+            return Err(Refusal::NotAllowed);
+        }
+        let Some(proposition) = self
+            .nick_name_proposition
+            .get_mut(&target)
+            .and_then(|nicknames| nicknames.iter_mut().find(|n| n.proposition == nickname))
+        else {
+            return Ok(());
+        };
+        if proposition.vetoed {
+            return Err(Refusal::Vetoed);
+        }
+
+        let reactors = proposition.reactions.entry(reaction).or_default();
+        match reactors.iter().position(|p| *p == reactor) {
+            Some(i) => {
+                reactors.swap_remove(i);
+            }
+            None => reactors.push(reactor),
+        }
+        if reactors.is_empty() {
+            proposition.reactions.remove(&reaction);
+        }
+        Ok(())
+    }
+
+    /// unlike votes, reacting to your own nicknames is always allowed
+    fn is_allowed_to_react(&self, reactor: ProfilID, target: ProfilID) -> bool {
+        self.get_permission(reactor).is_some_and(|permissions| {
+            self.is_action_allowed_between(
+                permissions.vote,
+                &permissions.scope_classes,
+                reactor,
+                target,
+            )
+        })
+    }
+
+    /// Attempt to describe a nickname proposition, only its author can
+    pub fn describe(
+        &mut self,
+        author: ProfilID,
+        target: ProfilID,
+        nickname: String,
+        description: String,
+    ) -> Result<(), Refusal> {
+        let description = description.trim();
+        if description.chars().count() > s2c::NicknameStatut::DESCRIPTION_MAX_LENGTH {
+            return Err(Refusal::DescriptionTooLong);
+        }
+        let Some(proposition) = self
+            .nick_name_proposition
+            .get_mut(&target)
+            .and_then(|nicknames| nicknames.iter_mut().find(|n| n.proposition == nickname))
+        else {
+            return Ok(());
+        };
+        if proposition.author != author {
+            return Err(Refusal::NotAllowed);
+        }
+        if proposition.vetoed {
+            return Err(Refusal::Vetoed);
+        }
+        proposition.description = Some(description.to_string()).filter(|d| !d.is_empty());
+        Ok(())
+    }
+
+    /// every nickname of the members of a class, from the most voted of each member
+    pub fn class_results(&self, class_name: &str) -> Result<Vec<ResultLine>, ServerError> {
+        let class = self
+            .classes
+            .values()
+            .find(|class| class.name == class_name)
+            .ok_or(ClassDoesntExist)?;
+        let name = |id: &ProfilID| {
+            self.id_to_profil
+                .get(id)
+                .map(|profil| profil.identity.name.clone())
+                .unwrap_or_default()
+        };
+
+        let mut targets: Vec<_> = class.profiles.iter().map(|id| (name(id), *id)).collect();
+        targets.sort_by(|(a, x), (b, y)| a.cmp(b).then(x.0.cmp(&y.0)));
+        let mut lines = vec![];
+        for (target_name, target) in targets {
+            let Some(propositions) = self.nick_name_proposition.get(&target) else {
+                continue;
+            };
+            let mut results: Vec<_> = propositions.iter().zip(self.counts(target)).collect();
+            results.sort_by_key(|(_, votes)| std::cmp::Reverse(*votes));
+            lines.extend(results.into_iter().map(|(proposition, votes)| ResultLine {
+                target: target_name.clone(),
+                nickname: proposition.proposition.clone(),
+                votes,
+                author: name(&proposition.author),
+                description: proposition.description.clone().unwrap_or_default(),
+                vetoed: proposition.vetoed,
+            }));
+        }
+        Ok(lines)
+    }
+
+    /// Attempt to comment a nickname proposition, empty comments are ignored
+    pub fn comment(
+        &mut self,
+        author: ProfilID,
+        target: ProfilID,
+        nickname: String,
+        text: String,
+    ) -> Result<(), Refusal> {
+        if !self.is_allowed_to_comment(author, target) {
+            return Err(Refusal::NotAllowed);
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if text.chars().count() > s2c::Comment::MAX_LENGTH {
+            return Err(Refusal::CommentTooLong);
+        }
+
+        let Some(proposition) = self
+            .nick_name_proposition
+            .get_mut(&target)
+            .and_then(|nicknames| nicknames.iter_mut().find(|n| n.proposition == nickname))
+        else {
+            return Ok(());
+        };
+        if proposition.vetoed {
+            return Err(Refusal::Vetoed);
+        }
+        if proposition.comments.len() >= s2c::Comment::MAX_PER_THREAD {
+            return Err(Refusal::ThreadFull);
+        }
+        let id = proposition.next_comment_id;
+        proposition.next_comment_id += 1;
+        proposition.comments.push(Comment {
+            id,
+            author,
+            text: text.to_string(),
+            created_at: now(),
+        });
+        Ok(())
+    }
+
+    fn is_allowed_to_comment(&self, author: ProfilID, target: ProfilID) -> bool {
+        self.get_permission(author).is_some_and(|permissions| {
+            self.is_action_allowed_between(
+                permissions.comment,
+                &permissions.scope_classes,
+                author,
+                target,
+            )
+        })
+    }
+
+    /// Attempt to delete a comment, only its author and the people allowed to protect the nicknames can.
+    /// Return whether it got deleted
+    pub fn delete_comment(
+        &mut self,
+        deleter: ProfilID,
+        target: ProfilID,
+        nickname: String,
+        comment: u32,
+    ) -> bool {
+        let (_, _, is_moderator) = self.get_permission_on_profil(deleter, target);
+        let Some(proposition) = self
+            .nick_name_proposition
+            .get_mut(&target)
+            .and_then(|nicknames| nicknames.iter_mut().find(|n| n.proposition == nickname))
+        else {
+            return false;
+        };
+        let before = proposition.comments.len();
+        proposition
+            .comments
+            .retain(|c| c.id != comment || (c.author != deleter && !is_moderator));
+        proposition.comments.len() != before
     }
 
     /// Attempt to protect a nickname proposition
@@ -563,7 +2059,13 @@ impl DataServer {
             return;
         };
 
-        if !self.is_action_allowed_between(permissions.protect_nickname, admin, target) {
+        if !self.is_action_allowed_between(
+            permissions.protect_nickname,
+            &permissions.scope_classes,
+            admin,
+            target,
+        ) && !self.is_class_admin_of(admin, target)
+        {
             return;
         }
 
@@ -576,22 +2078,64 @@ impl DataServer {
         nicknames[i].protected = new_statut;
     }
 
-    /// Return if a user can log
-    pub fn log(&self, identity: &Identity) -> Option<ProfilID> {
+    /// Return if a user can log, and record the login
+    pub fn log(&mut self, identity: &Identity) -> Option<ProfilID> {
         let Identity { name, password } = identity;
         let id = self.name_to_id.get(name)?;
-        let profil = self.id_to_profil.get(id)?;
+        let profil = self.id_to_profil.get_mut(id)?;
         if profil.identity.password == *password {
+            profil.login_history.last_login = Some(now());
+            profil.login_history.login_count += 1;
             Some(*id)
         } else {
+            profil.login_history.failed_login_count += 1;
             None
         }
     }
 
+    pub fn get_name(&self, id: ProfilID) -> Result<String, ServerError> {
+        let profil = self.id_to_profil.get(&id).ok_or(PersonDoesntExist)?;
+        Ok(profil.identity.name.clone())
+    }
+
     pub fn get_profil_id(&self, name: &String) -> Result<ProfilID, ServerError> {
         self.name_to_id.get(name).cloned().ok_or(PersonDoesntExist)
     }
 
+    /// compute an overview of the whole deployment
+    pub fn server_stats(&self) -> s2c::ServerStats {
+        const DAY: u64 = 24 * 60 * 60;
+        let yesterday = now().saturating_sub(DAY);
+
+        let (total_propositions, total_votes) = self.nick_name_proposition.values().flatten().fold(
+            (0, 0),
+            |(propositions, votes), proposition| {
+                (propositions + 1, votes + proposition.voters().count())
+            },
+        );
+
+        let mut lonely_people = self.find_people_out_of_any_class();
+        lonely_people.sort();
+
+        s2c::ServerStats {
+            total_profiles: self.id_to_profil.len(),
+            total_classes: self.classes.len(),
+            total_propositions,
+            total_votes,
+            daily_active_voters: self
+                .id_to_profil
+                .values()
+                .filter(|p| p.last_vote.is_some_and(|t| t >= yesterday))
+                .count(),
+            never_logged_in: self
+                .id_to_profil
+                .values()
+                .filter(|p| p.login_history.last_login.is_none())
+                .count(),
+            lonely_people,
+        }
+    }
+
     //------------ Network related functions ------------
 
     /// build the list of classes
@@ -610,10 +2154,7 @@ impl DataServer {
                             .iter()
                             .flat_map(|profil_id| {
                                 let profil = self.id_to_profil.get(profil_id)?;
-                                Some((
-                                    *profil_id,
-                                    profil.identity.name.clone(),
-                                ))
+                                Some((*profil_id, profil.public_name().to_string()))
                             })
                             .collect(),
                     },
@@ -623,16 +2164,22 @@ impl DataServer {
 
         let allowed_to_use_cmd = match profil {
             None => false,
-            Some(id) => self
-                .id_to_profil
-                .get(&id)
-                .map(|p| p.permissions.allowed_to_use_cmd)
-                .unwrap_or(false),
+            Some(id) => {
+                self.id_to_profil
+                    .get(&id)
+                    .map(|p| p.permissions.allowed_to_use_cmd || p.role.is_some())
+                    .unwrap_or(false)
+                    || self.is_class_admin(id)
+            }
         };
+        let own_profil = profil.and_then(|id| self.id_to_profil.get(&id));
         s2c::LoginResponse {
             classes,
             logged: profil.is_some(),
             allowed_to_use_cmd,
+            display_name: own_profil.map(|p| p.public_name().to_string()),
+            bio: own_profil.and_then(|p| p.bio.clone()),
+            refusal: None,
         }
     }
 
@@ -645,10 +2192,28 @@ impl DataServer {
         let Some(permission) = self.get_permission(requester) else {
             return (false, false, false);
         };
+        let is_class_admin = self.is_class_admin_of(requester, asked_profil);
         (
-            self.is_action_allowed_between(permission.vote, requester, asked_profil),
-            self.is_action_allowed_between(permission.delete, requester, asked_profil),
-            self.is_action_allowed_between(permission.protect_nickname, requester, asked_profil),
+            self.is_action_allowed_between(
+                permission.vote,
+                &permission.scope_classes,
+                requester,
+                asked_profil,
+            ) && (requester != asked_profil || self.are_self_votes_allowed(asked_profil)),
+            is_class_admin
+                || self.is_action_allowed_between(
+                    permission.delete,
+                    &permission.scope_classes,
+                    requester,
+                    asked_profil,
+                ),
+            is_class_admin
+                || self.is_action_allowed_between(
+                    permission.protect_nickname,
+                    &permission.scope_classes,
+                    requester,
+                    asked_profil,
+                ),
         )
     }
 
@@ -662,29 +2227,105 @@ impl DataServer {
             .map(|r| self.get_permission_on_profil(r, asked_profil))
             .unwrap_or((false, false, false));
 
+        let round = self.round_of(asked_profil);
+        let blind = self.is_blind_for(requester, asked_profil);
+        let visibility = self.visibility_of(asked_profil);
+        let sees_vetoed = self.sees_vetoed(requester, asked_profil);
+        let veto_budget = self.veto_budget_of(asked_profil);
+        let vetoes_left = (requester == Some(asked_profil) && veto_budget > 0)
+            .then(|| veto_budget.saturating_sub(self.vetoes_used(asked_profil)));
         let nicknames = self.nick_name_proposition.get(&asked_profil);
-        let nicknames = match nicknames {
+        let mut nicknames = match nicknames {
             None => vec![],
             Some(propositions) => propositions
                 .iter()
-                .map(|proposition| s2c::NicknameStatut {
+                .zip(self.counted_votes(asked_profil))
+                .filter(|(proposition, _)| !proposition.vetoed || sees_vetoed)
+                .map(|(proposition, votes)| s2c::NicknameStatut {
                     proposition: proposition.proposition.clone(),
-                    count: proposition.votes.len(),
-                    contain_you: requester
-                        .is_some_and(|requester| proposition.votes.contains(&requester)),
+                    count: match blind {
+                        true => 0,
+                        false => votes.len(),
+                    },
+                    voters: match visibility {
+                        VoteVisibility::Public if !blind => self.voter_names(&votes),
+                        _ => vec![],
+                    },
+                    contain_you: requester.is_some_and(|requester| {
+                        proposition.votes.contains(&requester)
+                            || proposition.ranks.iter().any(|(p, _)| *p == requester)
+                    }),
+                    your_votes: requester.map_or(0, |requester| {
+                        proposition
+                            .votes
+                            .iter()
+                            .filter(|p| **p == requester)
+                            .count() as u32
+                    }),
                     allowed_to_be_delete: (allowed_to_delete
                         || requester.is_some_and(|r| r == proposition.author))
-                        && (!proposition.protected || allowed_to_protect),
+                        && (!proposition.protected || allowed_to_protect)
+                        && round == 0,
                     protected: proposition.protected,
+                    comments: proposition
+                        .comments
+                        .iter()
+                        .map(|comment| s2c::Comment {
+                            id: comment.id,
+                            author: self
+                                .id_to_profil
+                                .get(&comment.author)
+                                .map(|profil| profil.public_name().to_string())
+                                .unwrap_or_default(),
+                            text: comment.text.clone(),
+                            created_at: comment.created_at,
+                            allowed_to_be_delete: allowed_to_protect
+                                || requester == Some(comment.author),
+                        })
+                        .collect(),
+                    description: proposition.description.clone(),
+                    allowed_to_describe: requester == Some(proposition.author),
+                    vetoed: proposition.vetoed,
+                    reactions: Reaction::ALL
+                        .into_iter()
+                        .filter_map(|reaction| {
+                            let reactors = proposition.reactions.get(&reaction)?;
+                            Some((reaction, reactors.len()))
+                        })
+                        .collect(),
+                    your_reactions: Reaction::ALL
+                        .into_iter()
+                        .filter(|reaction| {
+                            requester.is_some_and(|requester| {
+                                proposition
+                                    .reactions
+                                    .get(reaction)
+                                    .is_some_and(|reactors| reactors.contains(&requester))
+                            })
+                        })
+                        .collect(),
                 })
                 .collect(),
         };
+        // the order would reveal the leader, it has to stay the same between refreshes
+        if blind {
+            nicknames.sort_by(|a, b| a.proposition.cmp(&b.proposition));
+        }
 
         s2c::NicknameList {
             profil_id: asked_profil,
             nicknames,
             allowed_to_vote,
             allowed_to_protect,
+            allowed_to_comment: requester
+                .is_some_and(|r| self.is_allowed_to_comment(r, asked_profil)),
+            allowed_to_react: requester.is_some_and(|r| self.is_allowed_to_react(r, asked_profil)),
+            refusal: None,
+            voting_mode: self.voting_mode_of(asked_profil),
+            round: round as u32,
+            visibility,
+            blind,
+            vetoes_left,
         }
     }
 
@@ -693,6 +2334,8 @@ impl DataServer {
 
         Some(s2c::ProfilStats {
             profil_id: asked_profil,
+            display_name: profil.public_name().to_string(),
+            bio: profil.bio.clone(),
             total_votes: profil.total_votes,
             total_propositions: profil.total_propositions,
             numbers_of_nickname: self
@@ -0,0 +1,42 @@
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use structopt::clap::arg_enum;
+
+arg_enum! {
+    #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+    pub enum TokenScope {
+        ReadOnly,
+        Full,
+    }
+}
+
+/// Personal token used by scripts instead of a session, only its hash is kept
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub name: String,
+    /// sha256 of the token, in hex
+    pub hash: String,
+    pub scope: TokenScope,
+    /// unix timestamp
+    pub created_at: u64,
+}
+
+impl ApiToken {
+    const PREFIX: &'static str = "sv_";
+
+    /// return a fresh token along with its hash
+    pub fn generate() -> (String, String) {
+        let token = format!(
+            "{}{}",
+            Self::PREFIX,
+            Alphanumeric.sample_string(&mut rand::rng(), 40)
+        );
+        let hash = Self::hash(&token);
+        (token, hash)
+    }
+
+    pub fn hash(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+}
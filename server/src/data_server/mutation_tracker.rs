@@ -18,6 +18,10 @@ impl<T> MutationTracker<T> {
         Self { inner, dirty }
     }
 
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     pub fn clear_dirty(&mut self) -> bool {
         if self.dirty {
             self.dirty = false;
@@ -1,12 +1,19 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use structopt::clap::arg_enum;
 
+// OthersInSameClass: same class but not yourself
+// SpecificClasses: people of the classes listed in `Permissions::scope_classes`
+// SameClassGroup: same class, or a class of the same group
 arg_enum! {
     #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
     pub enum InteractionPermission {
         Forbidden,
         YourSelf,
         SameClass,
+        OthersInSameClass,
+        SpecificClasses,
+        SameClassGroup,
         AnyBody,
     }
 }
@@ -37,7 +44,7 @@ impl InteractionPermission {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Permissions {
     /// weather a user is allowed to vote/add nickname for someone
     #[serde(skip_serializing_if = "InteractionPermission::is_same_class")]
@@ -51,7 +58,16 @@ pub struct Permissions {
     #[serde(skip_serializing_if = "InteractionPermission::is_forbidden")]
     #[serde(default = "InteractionPermission::forbidden")]
     pub protect_nickname: InteractionPermission,
+    /// weather a user is allowed to comment someone nicknames, people allowed to protect them can delete the comments
+    #[serde(skip_serializing_if = "InteractionPermission::is_same_class")]
+    #[serde(default = "InteractionPermission::same_class")]
+    pub comment: InteractionPermission,
+    /// classes used by every `InteractionPermission::SpecificClasses`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub scope_classes: Vec<String>,
 
+    /// legacy access to every command, roles should be preferred
     #[serde(skip_serializing_if = "not")]
     #[serde(default = "bool::default")]
     pub allowed_to_use_cmd: bool,
@@ -67,6 +83,8 @@ impl Default for Permissions {
             vote: InteractionPermission::SameClass,
             delete: InteractionPermission::YourSelf,
             protect_nickname: InteractionPermission::Forbidden,
+            comment: InteractionPermission::SameClass,
+            scope_classes: Vec::new(),
             allowed_to_use_cmd: false,
         }
     }
@@ -77,3 +95,25 @@ impl Permissions {
         *self == Self::default()
     }
 }
+
+/// Named permissions which can be applied to many profils at once, the command permission is never part of it
+pub type Templates = HashMap<String, Permissions>;
+
+pub fn default_templates() -> Templates {
+    let student = Permissions::default();
+    let delegate = Permissions {
+        delete: InteractionPermission::SameClass,
+        ..Permissions::default()
+    };
+    let teacher = Permissions {
+        vote: InteractionPermission::Forbidden,
+        delete: InteractionPermission::SameClass,
+        protect_nickname: InteractionPermission::SameClass,
+        ..Permissions::default()
+    };
+    HashMap::from([
+        ("student".to_string(), student),
+        ("delegate".to_string(), delegate),
+        ("teacher".to_string(), teacher),
+    ])
+}
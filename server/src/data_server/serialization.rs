@@ -1,5 +1,7 @@
+use crate::data_server::api_tokens::ApiToken;
 use crate::data_server::permissions::Permissions;
-use common::{ClassID, Identity, ProfilID};
+use crate::data_server::LoginHistory;
+use common::{ClassID, Identity, ProfilID, VoteVisibility, VotingMode};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -9,6 +11,24 @@ pub struct Profil {
     #[serde(default)]
     #[serde(skip_serializing_if = "Permissions::is_default")]
     pub permissions: Permissions,
+    /// unix timestamp
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_vote: Option<u64>,
+    #[serde(flatten)]
+    pub login_history: LoginHistory,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_tokens: Vec<ApiToken>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -27,4 +47,36 @@ pub struct IdMap {
 pub struct Class {
     pub name: String,
     pub people: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_template: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_self_votes: Option<bool>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_single")]
+    pub voting_mode: VotingMode,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_counts")]
+    pub visibility: VoteVisibility,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub blind: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "super::is_zero")]
+    pub veto_budget: u32,
+}
+
+fn is_single(voting_mode: &VotingMode) -> bool {
+    *voting_mode == VotingMode::Single
+}
+
+fn is_counts(visibility: &VoteVisibility) -> bool {
+    *visibility == VoteVisibility::Counts
 }
@@ -0,0 +1,63 @@
+/// The candidates of a ballot, from the preferred one, as indexes in the candidate list
+pub type Ballot = Vec<usize>;
+
+/// Outcome of an instant-runoff tally
+pub struct Runoff {
+    /// first preferences of the candidates still running, for each round
+    pub rounds: Vec<Vec<(usize, u32)>>,
+    pub winner: Option<usize>,
+}
+
+/// Borda count, a candidate gets `candidates - 1 - rank` points per ballot and nothing when unranked
+pub fn borda(candidates: usize, ballots: &[Ballot]) -> Vec<u32> {
+    let mut scores = vec![0; candidates];
+    for ballot in ballots {
+        for (rank, candidate) in ballot.iter().enumerate() {
+            scores[*candidate] += (candidates - 1 - rank) as u32;
+        }
+    }
+    scores
+}
+
+/// Count first preferences, eliminate the last candidate and transfer its ballots until someone has a majority.
+/// Ties for the elimination are broken by the Borda count, then the most recent candidate goes first.
+pub fn instant_runoff(candidates: usize, ballots: &[Ballot]) -> Runoff {
+    let scores = borda(candidates, ballots);
+    let mut running = vec![true; candidates];
+    let mut rounds = vec![];
+
+    loop {
+        let mut counts = vec![0; candidates];
+        for ballot in ballots {
+            // exhausted ballots are not counted anymore
+            if let Some(first) = ballot.iter().find(|c| running[**c]) {
+                counts[*first] += 1;
+            }
+        }
+        let round: Vec<(usize, u32)> = (0..candidates)
+            .filter(|c| running[*c])
+            .map(|c| (c, counts[c]))
+            .collect();
+        let total: u32 = round.iter().map(|(_, count)| count).sum();
+        rounds.push(round.clone());
+
+        if total == 0 {
+            return Runoff {
+                rounds,
+                winner: None,
+            };
+        }
+        if let Some((winner, _)) = round.iter().find(|(_, count)| count * 2 > total) {
+            return Runoff {
+                winner: Some(*winner),
+                rounds,
+            };
+        }
+
+        let (last, _) = round
+            .iter()
+            .min_by(|(a, x), (b, y)| x.cmp(y).then(scores[*a].cmp(&scores[*b])).then(b.cmp(a)))
+            .unwrap(); // there is at least 2 candidates, otherwise the only one would have the majority
+        running[*last] = false;
+    }
+}
@@ -0,0 +1,73 @@
+use actix_web::rt::time::timeout;
+use actix_web::web::Bytes;
+use common::ProfilID;
+use futures_util::{stream, Stream};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Broadcast which profil got its nicknames modified, so connected clients can refresh their view.
+/// None means it could be anyone
+pub struct LiveUpdates {
+    sender: broadcast::Sender<Option<ProfilID>>,
+}
+
+impl LiveUpdates {
+    const CAPACITY: usize = 256;
+    /// proxies tend to close silent connections
+    const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(Self::CAPACITY);
+        Self { sender }
+    }
+
+    pub fn notify(&self, profil: ProfilID) {
+        // nobody listening is not an error
+        let _ = self.sender.send(Some(profil));
+    }
+
+    pub fn notify_everyone(&self) {
+        let _ = self.sender.send(None);
+    }
+
+    /// Server-Sent Events stream, `render` is emitted on connection and each time `profil` get modified
+    pub fn event_stream<F>(
+        &self,
+        profil: ProfilID,
+        render: F,
+    ) -> impl Stream<Item = Result<Bytes, Infallible>>
+    where
+        F: Fn() -> Option<String>,
+    {
+        let receiver = self.sender.subscribe();
+        stream::unfold(
+            (receiver, render, true),
+            move |(mut receiver, render, first)| async move {
+                if first {
+                    let event = Self::event(render());
+                    return Some((Ok(event), (receiver, render, false)));
+                }
+
+                loop {
+                    let event = match timeout(Self::KEEP_ALIVE, receiver.recv()).await {
+                        Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+                        Ok(Ok(Some(modified))) if modified != profil => continue,
+                        // when lagging behind, we might have missed our profil
+                        Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => Self::event(render()),
+                        Ok(Err(RecvError::Closed)) => return None,
+                    };
+                    return Some((Ok(event), (receiver, render, false)));
+                }
+            },
+        )
+    }
+
+    fn event(data: Option<String>) -> Bytes {
+        match data {
+            Some(data) => Bytes::from(format!("data: {data}\n\n")),
+            None => Bytes::from_static(b": nothing to send\n\n"),
+        }
+    }
+}
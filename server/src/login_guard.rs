@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoginProtectionConfig {
+    /// delay imposed after the first failed attempt, doubled on each new failure
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// failed attempts on a single account before it gets locked
+    pub max_attempts_per_account: u32,
+    /// failed attempts from a single ip before it gets locked, a whole school can share an ip so there is no backoff here
+    pub max_attempts_per_ip: u32,
+    pub lockout_duration: Duration,
+}
+
+impl Default for LoginProtectionConfig {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts_per_account: 10,
+            max_attempts_per_ip: 50,
+            lockout_duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+struct Attempts {
+    failures: u32,
+    blocked_until: Instant,
+}
+
+/// Keep track of failed logins, per account and per ip, to slow down password guessing
+pub struct LoginGuard {
+    config: LoginProtectionConfig,
+    accounts: HashMap<String, Attempts>,
+    ips: HashMap<IpAddr, Attempts>,
+}
+
+impl LoginGuard {
+    pub fn new(config: LoginProtectionConfig) -> Self {
+        Self {
+            config,
+            accounts: HashMap::new(),
+            ips: HashMap::new(),
+        }
+    }
+
+    /// return how long the caller must wait before trying again, if it must
+    pub fn check(&self, name: &str, ip: Option<IpAddr>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let account = self.accounts.get(name).map(|a| a.blocked_until);
+        let ip = ip.and_then(|ip| self.ips.get(&ip)).map(|a| a.blocked_until);
+
+        match account.into_iter().chain(ip).max() {
+            Some(blocked_until) if blocked_until > now => Err(blocked_until - now),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&mut self, name: &str, ip: Option<IpAddr>) {
+        let now = Instant::now();
+        self.purge(now);
+
+        let max = self.config.max_attempts_per_account;
+        if Self::fail(
+            &self.config,
+            &mut self.accounts,
+            name.to_string(),
+            max,
+            true,
+            now,
+        ) {
+            warn!("account {name} locked after {max} failed login attempts");
+        }
+
+        if let Some(ip) = ip {
+            let max = self.config.max_attempts_per_ip;
+            if Self::fail(&self.config, &mut self.ips, ip, max, false, now) {
+                warn!("ip {ip} locked after {max} failed login attempts");
+            }
+        }
+    }
+
+    /// a successful login clear the account history, but not the ip one, otherwise an attacker could reset it with their own account
+    pub fn record_success(&mut self, name: &str) {
+        self.accounts.remove(name);
+    }
+
+    /// return false if the account wasn't being tracked
+    pub fn unlock(&mut self, name: &str) -> bool {
+        let unlocked = self.accounts.remove(name).is_some();
+        if unlocked {
+            info!("account {name} unlocked");
+        }
+        unlocked
+    }
+
+    /// register a failure, return true if the key just got locked
+    fn fail<K: Eq + Hash>(
+        config: &LoginProtectionConfig,
+        map: &mut HashMap<K, Attempts>,
+        key: K,
+        max_attempts: u32,
+        backoff: bool,
+        now: Instant,
+    ) -> bool {
+        let attempts = map.entry(key).or_insert(Attempts {
+            failures: 0,
+            blocked_until: now,
+        });
+
+        // a lockout that expired gives a fresh start
+        if attempts.failures >= max_attempts && attempts.blocked_until <= now {
+            attempts.failures = 0;
+        }
+
+        attempts.failures += 1;
+        if attempts.failures >= max_attempts {
+            attempts.blocked_until = now + config.lockout_duration;
+            return attempts.failures == max_attempts;
+        }
+
+        if backoff {
+            let delay = config
+                .base_delay
+                .saturating_mul(1 << (attempts.failures - 1).min(16))
+                .min(config.max_delay);
+            attempts.blocked_until = now + delay;
+        }
+        false
+    }
+
+    /// forget about keys which have been quiet for long enough
+    fn purge(&mut self, now: Instant) {
+        let lockout_duration = self.config.lockout_duration;
+        let keep = |attempts: &mut Attempts| attempts.blocked_until + lockout_duration > now;
+        self.accounts.retain(|_, attempts| keep(attempts));
+        self.ips.retain(|_, attempts| keep(attempts));
+    }
+}
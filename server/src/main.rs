@@ -1,45 +1,75 @@
+mod benchmark;
 mod commands;
 mod data_server;
+mod live_updates;
+mod login_guard;
+mod rate_limit;
+mod requester;
+mod roles;
+mod session_store;
 
 use crate::commands::{
-    AddClass, AddLonelyToClass, AddProfil, AddToClass, ChangeName, ChangePassword,
-    ChangePermission, DeleteClass, DeleteProfil, PermissionKind, RemoveFromClass, ViewPassword,
+    AddClass, AddClassAdmin, AddLonelyToClass, AddProfil, AddToClass, ApplyTemplate, Benchmark,
+    ChangeName, ChangePassword, ChangePermission, CreateToken, DeleteClass, DeleteProfil,
+    DeleteTemplate, EditTemplate, ExportResults, ListSessions, ListTokens, ListTrash,
+    PermissionKind, RemoveClassAdmin, RemoveFromClass, RestoreNickname, RevealResults,
+    RevokeSessions, RevokeToken, SetClassBlind, SetClassGroup, SetClassSelfVotes, SetClassTemplate,
+    SetVetoBudget, SetVoteVisibility, SetVotingMode, StartFinal, UnlockAccount, ViewArchive,
+    ViewInactive, ViewPassword, ViewSelfVotes, VisibilityKind, VotingModeKind,
 };
-use crate::data_server::permissions::Permissions;
-use crate::data_server::{DataServer, NickNameProposition, ServerError};
+use crate::data_server::api_tokens::TokenScope;
+use crate::data_server::permissions::Templates;
+use crate::data_server::{
+    now, serialization, Archives, DataServer, NickNameProposition, ServerError, Trash,
+};
+use crate::live_updates::LiveUpdates;
+use crate::login_guard::{LoginGuard, LoginProtectionConfig};
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::requester::{Access, Requester};
+use crate::roles::{Role, Roles, Targets};
+use crate::session_store::{PersistentSessionStore, Sessions};
 use actix_cors::Cors;
 use actix_files::Files;
 use actix_identity::IdentityMiddleware;
-use actix_session::storage::CookieSessionStore;
+use actix_session::config::PersistentSession;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
+use actix_web::http::header::{CacheControl, CacheDirective, RETRY_AFTER};
 use actix_web::http::KeepAlive;
+use actix_web::middleware::from_fn;
 use actix_web::{
     web, web::ServiceConfig, App, Either, HttpMessage, HttpRequest, HttpResponse, HttpServer,
     Responder,
 };
+use base64::prelude::{Engine, BASE64_STANDARD};
 use common::packets::c2s;
 use common::packets::c2s::{
-    AskForNicknameList, AskForProfilStats, CommandInput, DeleteNickname, Login,
-    UpdateNicknameProtection, VoteNickname,
+    AskForNicknameList, AskForProfilStats, CommandInput, CommentNickname, DeleteComment,
+    DeleteNickname, DescribeNickname, Login, RankNicknames, ReactNickname,
+    UpdateNicknameProtection, VetoNickname, VoteNickname,
 };
 use common::packets::s2c::CommandResponse;
-use common::ProfilID;
+use common::{ProfilID, VoteVisibility, VotingMode};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::stdin;
+use std::io;
+use std::io::{stdin, BufWriter, Write};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::sync::Mutex;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 use tokio::task::spawn_blocking;
-use tracing::info;
+use tracing::{error, info};
 
 extern crate tracing;
 
-type State = Mutex<AppState>;
+type State = RwLock<AppState>;
+
+/// where `export-results` writes its files
+const EXPORT_DIRECTORY: &str = "exports";
 
 #[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
 enum SaveFormat {
@@ -47,44 +77,106 @@ enum SaveFormat {
     Json,
 }
 
+/// Someone running a command from the client, the server console has none and can do anything
+struct Caller<'a> {
+    id: ProfilID,
+    command_name: &'a str,
+}
+
 struct AppState {
     data_server: DataServer,
     save_format: SaveFormat,
+    login_guard: LoginGuard,
+    sessions: PersistentSessionStore,
+    roles: Roles,
 }
 
-impl AppState {
-    fn save(&mut self) {
+/// Everything that has to be written on disk, copied out of the state so the lock can be released before serializing
+struct SaveSnapshot {
+    save_format: SaveFormat,
+    nicknames: Option<HashMap<ProfilID, Vec<NickNameProposition>>>,
+    profils: Option<(serialization::PeopleRepartition, serialization::IdMap)>,
+    sessions: Option<Sessions>,
+    templates: Option<Templates>,
+    archives: Option<Archives>,
+    trash: Option<Trash>,
+}
+
+impl SaveSnapshot {
+    fn is_empty(&self) -> bool {
+        self.nicknames.is_none()
+            && self.profils.is_none()
+            && self.sessions.is_none()
+            && self.templates.is_none()
+            && self.archives.is_none()
+            && self.trash.is_none()
+    }
+
+    fn write_file<T: Serialize>(&self, name: &str, content: &T) -> io::Result<()> {
         match self.save_format {
             SaveFormat::Json => {
-                if let Some(nicknames) = self.data_server.try_to_save_nickname() {
-                    let file = File::create("nicknames.json").unwrap();
-                    serde_json::to_writer_pretty(file, &nicknames).unwrap()
-                }
-
-                if let Some((repartition, id_map)) = self.data_server.try_to_save_profils() {
-                    let file = File::create("classes.json").unwrap();
-                    serde_json::to_writer_pretty(file, &repartition).unwrap();
-                    let file = File::create("id_map.json").unwrap();
-                    serde_json::to_writer_pretty(file, &id_map).unwrap();
-                }
+                let mut file = BufWriter::new(File::create(format!("{name}.json"))?);
+                serde_json::to_writer_pretty(&mut file, content).map_err(io::Error::other)?;
+                file.flush()
             }
-
             SaveFormat::Cbor => {
-                if let Some(nicknames) = self.data_server.try_to_save_nickname() {
-                    let file = File::create("nicknames.cbor").unwrap();
-                    ciborium::into_writer(&nicknames, file).unwrap()
-                }
-
-                if let Some((repartition, id_map)) = self.data_server.try_to_save_profils() {
-                    let file = File::create("classes.cbor").unwrap();
-                    ciborium::into_writer(&repartition, file).unwrap();
-                    let file = File::create("id_map.cbor").unwrap();
-                    ciborium::into_writer(&id_map, file).unwrap();
-                }
+                let mut file = BufWriter::new(File::create(format!("{name}.cbor"))?);
+                ciborium::into_writer(content, &mut file).map_err(io::Error::other)?;
+                file.flush()
             }
         }
     }
 
+    /// serialize and write everything, this is blocking and should be run outside of the async runtime
+    fn write(self) -> io::Result<()> {
+        if let Some(nicknames) = &self.nicknames {
+            self.write_file("nicknames", nicknames)?;
+        }
+
+        if let Some((repartition, id_map)) = &self.profils {
+            self.write_file("classes", repartition)?;
+            self.write_file("id_map", id_map)?;
+        }
+
+        if let Some(sessions) = &self.sessions {
+            self.write_file("sessions", sessions)?;
+        }
+
+        if let Some(templates) = &self.templates {
+            self.write_file("templates", templates)?;
+        }
+
+        if let Some(archives) = &self.archives {
+            self.write_file("archives", archives)?;
+        }
+
+        if let Some(trash) = &self.trash {
+            self.write_file("trash", trash)?;
+        }
+        Ok(())
+    }
+}
+
+impl AppState {
+    /// copy every dirty part of the state, this should be quick since it's done under the lock
+    fn snapshot(&mut self) -> SaveSnapshot {
+        SaveSnapshot {
+            save_format: self.save_format,
+            nicknames: self.data_server.try_to_save_nickname(),
+            profils: self.data_server.try_to_save_profils(),
+            sessions: self.sessions.try_to_save(),
+            templates: self.data_server.try_to_save_templates(),
+            archives: self.data_server.try_to_save_archives(),
+            trash: self.data_server.try_to_save_trash(),
+        }
+    }
+
+    /// the dirty flags were cleared by the snapshot, everything has to be written again
+    fn mark_unsaved(&mut self) {
+        self.data_server.mark_unsaved();
+        self.sessions.mark_unsaved();
+    }
+
     /// return which file is the more recent, if unable to compare, return None,
     fn is_more_recent_than(f1: &File, f2: &File) -> Option<bool> {
         let time1 = f1.metadata().ok()?.modified().ok()?;
@@ -119,7 +211,7 @@ impl AppState {
         }
     }
 
-    fn new(save_format: SaveFormat) -> Mutex<Self> {
+    fn new(save_format: SaveFormat, login_guard: LoginGuard, roles: Roles) -> State {
         let people_repartition =
             Self::load_data(save_format, "classes").unwrap_or(Default::default());
         let id_map = Self::load_data(save_format, "id_map").unwrap_or(Default::default());
@@ -132,26 +224,217 @@ impl AppState {
             data_server.load_proposition(nicknames);
         }
 
+        if let Some(templates) = Self::load_data(save_format, "templates") {
+            data_server.load_templates(templates);
+        }
+
+        if let Some(archives) = Self::load_data(save_format, "archives") {
+            data_server.load_archives(archives);
+        }
+
+        if let Some(trash) = Self::load_data(save_format, "trash") {
+            data_server.load_trash(trash);
+        }
+
         if let Some(generated_id_map) = data_server.build_id_map() {
             let file = File::create("id_map.json").expect("Failed to create a id_map file");
             serde_json::to_writer_pretty(file, &generated_id_map).unwrap();
         }
 
-        Mutex::new(AppState {
+        let sessions = Self::load_data(save_format, "sessions").unwrap_or_default();
+
+        RwLock::new(AppState {
             data_server,
             save_format,
+            login_guard,
+            sessions: PersistentSessionStore::new(sessions),
+            roles,
         })
     }
 
-    fn execute_command(&mut self, command: Commands) -> Result<Option<String>, ServerError> {
+    /// the role of a profil, profils with the legacy command permission are superadmins
+    fn role_of(&self, id: ProfilID) -> Option<&Role> {
+        let server = &self.data_server;
+        let name = match server.get_role(id) {
+            Some(name) => name,
+            None if server.get_permission(id)?.allowed_to_use_cmd => Role::LEGACY,
+            None => return None,
+        };
+        self.roles.get(name)
+    }
+
+    /// check if someone can run a command, either through their role or as a class admin
+    fn authorize(
+        &self,
+        id: ProfilID,
+        command_name: &str,
+        targets: Option<Targets>,
+    ) -> Result<(), &'static str> {
+        let by_role = self.authorize_role(id, command_name, targets.as_ref());
+        if by_role.is_err() && self.authorize_class_admin(id, command_name, targets.as_ref()) {
+            return Ok(());
+        }
+        if self.role_of(id).is_none() && self.data_server.is_class_admin(id) {
+            return Err("class admins can only add, remove and reset the password of the people of their classes");
+        }
+        by_role
+    }
+
+    /// someone the profil shouldn't be able to act on unless superadmin
+    fn is_privileged(&self, id: ProfilID) -> bool {
+        self.role_of(id).is_some() || self.data_server.is_class_admin(id)
+    }
+
+    /// class admins can manage the members of their classes
+    fn authorize_class_admin(
+        &self,
+        id: ProfilID,
+        command_name: &str,
+        targets: Option<&Targets>,
+    ) -> bool {
+        const CLASS_ADMIN_COMMANDS: [&str; 3] =
+            ["add-to-class", "remove-from-class", "change-password"];
+        let server = &self.data_server;
+        let Some(targets) = targets else {
+            return false;
+        };
+
+        CLASS_ADMIN_COMMANDS.contains(&command_name)
+            && targets
+                .classes
+                .iter()
+                .all(|class| server.is_admin_of_class(id, class))
+            && targets.profils.iter().all(|profil| {
+                server.get_profil_id(profil).is_ok_and(|target| {
+                    // people out of any class can be taken in, nothing else
+                    !self.is_privileged(target)
+                        && (server.is_class_admin_of(id, target)
+                            || (command_name == "add-to-class" && !server.is_in_any_class(target)))
+                })
+            })
+    }
+
+    /// targets are None when the command acts on the whole server
+    fn authorize_role(
+        &self,
+        id: ProfilID,
+        command_name: &str,
+        targets: Option<&Targets>,
+    ) -> Result<(), &'static str> {
+        let server = &self.data_server;
+        let role = self
+            .role_of(id)
+            .ok_or("you are not allowed to use commands")?;
+        if !role.allows(command_name) {
+            return Err("your role does not allow this command");
+        }
+        if role.is_superadmin() {
+            return Ok(());
+        }
+
+        let Some(targets) = targets else {
+            return if role.class_scoped {
+                Err("your role is limited to your classes, this command acts on the whole server")
+            } else {
+                Ok(())
+            };
+        };
+
+        for profil in targets.profils.iter() {
+            let Ok(target) = server.get_profil_id(profil) else {
+                // the command will fail on its own
+                continue;
+            };
+            // otherwise a moderator could take over an admin account
+            if self.is_privileged(target) {
+                return Err("only a superadmin can act on someone with a role");
+            }
+            // people out of any class are out of reach too
+            if role.class_scoped && !server.are_in_same_class(id, target) {
+                return Err("your role is limited to the people of your classes");
+            }
+        }
+
+        if role.class_scoped
+            && targets
+                .classes
+                .iter()
+                .any(|class| !server.is_in_class(id, class))
+        {
+            return Err("your role is limited to your classes");
+        }
+        Ok(())
+    }
+
+    /// profils whose nicknames can change with a command, None when it can be anyone's
+    fn affected_profils(&self, command: &Commands) -> Option<Vec<ProfilID>> {
+        if command.is_read_only() {
+            return Some(vec![]);
+        }
+        // the votes and the name of a profil are shown in the lists of the others
+        if let Commands::DeleteProfil(_) | Commands::ChangeName(_) = command {
+            return None;
+        }
+
+        let server = &self.data_server;
+        let targets = command.targets()?;
+        let mut profils: Vec<_> = targets
+            .profils
+            .iter()
+            .filter_map(|name| server.get_profil_id(name).ok())
+            .collect();
+        for class in targets.classes {
+            profils.extend(server.class_members(class));
+        }
+        Some(profils)
+    }
+
+    fn execute_command(
+        &mut self,
+        command: Commands,
+        caller: Option<Caller>,
+        updates: &LiveUpdates,
+    ) -> Result<Option<String>, ServerError> {
+        if let Some(Caller { id, command_name }) = caller {
+            if let Err(e) = self.authorize(id, command_name, command.targets()) {
+                return Ok(Some(e.to_string()));
+            }
+        }
+
+        // resolved before the command renames or deletes anything
+        let affected = self.affected_profils(&command);
+        let result = self.run_command(command);
+        if result.is_ok() {
+            match affected {
+                Some(profils) => profils.into_iter().for_each(|p| updates.notify(p)),
+                None => updates.notify_everyone(),
+            }
+        }
+        result
+    }
+
+    fn run_command(&mut self, command: Commands) -> Result<Option<String>, ServerError> {
         let server = &mut self.data_server;
         match command {
+            Commands::UnlockAccount(UnlockAccount { name }) => {
+                server.get_profil_id(&name)?;
+                if self.login_guard.unlock(&name) {
+                    Ok(None)
+                } else {
+                    Ok(Some(format!("{name} wasn't locked")))
+                }
+            }
             Commands::Exit => Ok(Some("You can't shutdown the server from here".to_string())),
+            Commands::Benchmark(_) => Ok(Some(
+                "The benchmark can only be run from the server console".to_string(),
+            )),
             Commands::AddProfil(AddProfil { name, password }) => {
                 server.add_profile(name, password).map(|_| None)
             }
             Commands::DeleteProfil(DeleteProfil { name }) => {
-                server.delete_profil(name).map(|_| None)
+                server.delete_profil(name.clone())?;
+                self.sessions.revoke(&name);
+                Ok(None)
             }
             Commands::AddClass(AddClass { name }) => server.add_class(name).map(|_| None),
             Commands::DeleteClass(DeleteClass { name }) => server.delete_class(name).map(|_| None),
@@ -169,6 +452,62 @@ impl AppState {
                 }
                 Ok(Some(output))
             }
+            Commands::ViewStats => {
+                use std::fmt::Write;
+
+                let stats = server.server_stats();
+                let mut output = String::new();
+                writeln!(&mut output, "profiles: {}", stats.total_profiles).unwrap();
+                writeln!(&mut output, "classes: {}", stats.total_classes).unwrap();
+                writeln!(&mut output, "propositions: {}", stats.total_propositions).unwrap();
+                writeln!(&mut output, "votes: {}", stats.total_votes).unwrap();
+                writeln!(
+                    &mut output,
+                    "daily active voters: {}",
+                    stats.daily_active_voters
+                )
+                .unwrap();
+                writeln!(&mut output, "never logged in: {}", stats.never_logged_in).unwrap();
+                writeln!(
+                    &mut output,
+                    "lonely people ({}): {}",
+                    stats.lonely_people.len(),
+                    stats.lonely_people.join(", ")
+                )
+                .unwrap();
+                Ok(Some(output))
+            }
+            Commands::ViewInactive(ViewInactive { class, days }) => {
+                use std::fmt::Write;
+
+                const DAY: u64 = 24 * 60 * 60;
+                let now = now();
+                let since = days.map(|days| now.saturating_sub(days * DAY));
+                let peoples = server.find_inactive_profils(class.as_deref(), since)?;
+                let mut output = String::new();
+                if peoples.is_empty() {
+                    writeln!(&mut output, "No people found!").unwrap();
+                }
+                for (name, history) in peoples {
+                    match history.last_login {
+                        None => write!(&mut output, "{name}: never logged in").unwrap(),
+                        Some(last_login) => write!(
+                            &mut output,
+                            "{name}: last login {} day(s) ago, {} login(s)",
+                            now.saturating_sub(last_login) / DAY,
+                            history.login_count
+                        )
+                        .unwrap(),
+                    }
+                    writeln!(
+                        &mut output,
+                        ", {} failed attempt(s)",
+                        history.failed_login_count
+                    )
+                    .unwrap();
+                }
+                Ok(Some(output))
+            }
             Commands::AddLonelyPeopleToClass(AddLonelyToClass { class }) => {
                 let people = server.find_id_out_of_any_class();
                 for id in people {
@@ -184,8 +523,72 @@ impl AppState {
             Commands::ChangePassword(ChangePassword { name, new_password }) => {
                 let id = server.get_profil_id(&name)?;
                 server.change_password(id, new_password)?;
+                // the password may have leaked, every device has to log in again
+                self.sessions.revoke(&name);
                 Ok(None)
             }
+            Commands::ListSessions(ListSessions { name }) => {
+                use std::fmt::Write;
+
+                server.get_profil_id(&name)?;
+                let sessions = self.sessions.list(&name);
+                let mut output = String::new();
+                if sessions.is_empty() {
+                    writeln!(&mut output, "No session found!").unwrap();
+                }
+                let now = now();
+                for (hash, expires_at) in sessions {
+                    writeln!(
+                        &mut output,
+                        "{hash}...: expires in {} hour(s)",
+                        expires_at.saturating_sub(now) / 3600
+                    )
+                    .unwrap();
+                }
+                Ok(Some(output))
+            }
+            Commands::RevokeSessions(RevokeSessions { name }) => {
+                server.get_profil_id(&name)?;
+                let revoked = self.sessions.revoke(&name);
+                Ok(Some(format!("{revoked} session(s) revoked")))
+            }
+            Commands::CreateToken(CreateToken {
+                name,
+                token_name,
+                scope,
+            }) => {
+                let id = server.get_profil_id(&name)?;
+                let token = server.create_api_token(id, token_name, scope)?;
+                Ok(Some(format!(
+                    "{scope} token created, it won't be shown again: {token}"
+                )))
+            }
+            Commands::ListTokens(ListTokens { name }) => {
+                use std::fmt::Write;
+
+                let id = server.get_profil_id(&name)?;
+                let tokens = server.list_api_tokens(id)?;
+                let mut output = String::new();
+                if tokens.is_empty() {
+                    writeln!(&mut output, "No token found!").unwrap();
+                }
+                let now = now();
+                for token in tokens {
+                    writeln!(
+                        &mut output,
+                        "{}: {}, created {} day(s) ago",
+                        token.name,
+                        token.scope,
+                        now.saturating_sub(token.created_at) / (24 * 60 * 60)
+                    )
+                    .unwrap();
+                }
+                Ok(Some(output))
+            }
+            Commands::RevokeToken(RevokeToken { name, token_name }) => {
+                let id = server.get_profil_id(&name)?;
+                server.revoke_api_token(id, &token_name).map(|_| None)
+            }
             Commands::ChangeName(ChangeName { name, new_name }) => {
                 server.change_name(name, new_name).map(|_| None)
             }
@@ -205,6 +608,238 @@ impl AppState {
                 server.remove_from_class(id, class_name)?;
                 Ok(None)
             }
+            Commands::AddClassAdmin(AddClassAdmin {
+                profil_name,
+                class_name,
+            }) => {
+                let id = server.get_profil_id(&profil_name)?;
+                server.add_class_admin(id, &class_name)?;
+                Ok(None)
+            }
+            Commands::RemoveClassAdmin(RemoveClassAdmin {
+                profil_name,
+                class_name,
+            }) => {
+                let id = server.get_profil_id(&profil_name)?;
+                server.remove_class_admin(id, &class_name)?;
+                Ok(None)
+            }
+            Commands::ListTemplates => {
+                use std::fmt::Write;
+
+                let mut templates: Vec<_> = server.templates().iter().collect();
+                templates.sort_by_key(|(name, _)| *name);
+                let mut output = String::new();
+                if templates.is_empty() {
+                    writeln!(&mut output, "No template found!").unwrap();
+                }
+                for (name, permissions) in templates {
+                    write!(
+                        &mut output,
+                        "{name}: vote {}, delete {}, protect {}, comment {}",
+                        permissions.vote,
+                        permissions.delete,
+                        permissions.protect_nickname,
+                        permissions.comment
+                    )
+                    .unwrap();
+                    if !permissions.scope_classes.is_empty() {
+                        write!(
+                            &mut output,
+                            ", scope classes {}",
+                            permissions.scope_classes.join(", ")
+                        )
+                        .unwrap();
+                    }
+                    writeln!(&mut output).unwrap();
+                }
+                Ok(Some(output))
+            }
+            Commands::EditTemplate(EditTemplate { template, kind }) => {
+                match kind {
+                    PermissionKind::Vote { permission } => {
+                        server.template_mut(template).vote = permission
+                    }
+                    PermissionKind::Delete { permission } => {
+                        server.template_mut(template).delete = permission
+                    }
+                    PermissionKind::Protect { permission } => {
+                        server.template_mut(template).protect_nickname = permission
+                    }
+                    PermissionKind::Comment { permission } => {
+                        server.template_mut(template).comment = permission
+                    }
+                    PermissionKind::ScopeClasses { classes } => {
+                        server.template_mut(template).scope_classes = classes
+                    }
+                    PermissionKind::UseCmd { .. } | PermissionKind::Role { .. } => {
+                        return Ok(Some("Templates can't give access to commands".to_string()));
+                    }
+                }
+                Ok(None)
+            }
+            Commands::DeleteTemplate(DeleteTemplate { template }) => {
+                server.delete_template(&template).map(|_| None)
+            }
+            Commands::ApplyTemplate(ApplyTemplate { template, class }) => {
+                let count = server.apply_template(&template, &class)?;
+                Ok(Some(format!("{count} profil(s) updated")))
+            }
+            Commands::SetClassTemplate(SetClassTemplate { class, template }) => {
+                server.set_class_template(&class, template).map(|_| None)
+            }
+            Commands::SetClassSelfVotes(SetClassSelfVotes { class, allowed }) => server
+                .set_class_allow_self_votes(&class, allowed)
+                .map(|_| None),
+            Commands::ViewSelfVotes(ViewSelfVotes { purge }) => {
+                use std::fmt::Write;
+
+                let self_votes = server.find_self_votes();
+                let mut output = String::new();
+                if self_votes.is_empty() {
+                    writeln!(&mut output, "No self vote found!").unwrap();
+                }
+                for (id, nickname) in self_votes {
+                    let name = server.get_name(id)?;
+                    writeln!(&mut output, "{name}: {nickname}").unwrap();
+                }
+                if purge {
+                    let purged = server.purge_self_votes();
+                    writeln!(&mut output, "{purged} vote(s) purged").unwrap();
+                }
+                Ok(Some(output))
+            }
+            Commands::SetVoteVisibility(SetVoteVisibility { class, visibility }) => {
+                let visibility = match visibility {
+                    VisibilityKind::Counts => VoteVisibility::Counts,
+                    VisibilityKind::Public => VoteVisibility::Public,
+                };
+                server.set_visibility(&class, visibility).map(|_| None)
+            }
+            Commands::SetClassBlind(SetClassBlind { class, blind }) => {
+                server.set_class_blind(&class, blind).map(|_| None)
+            }
+            Commands::SetVetoBudget(SetVetoBudget { class, budget }) => {
+                server.set_veto_budget(&class, budget).map(|_| None)
+            }
+            Commands::RevealResults(RevealResults { class }) => {
+                server.set_class_blind(&class, false).map(|_| None)
+            }
+            Commands::StartFinal(StartFinal { class, top }) => {
+                let started = server.start_final(&class, top)?;
+                Ok(Some(format!("final started for {started} profil(s)")))
+            }
+            Commands::ExportResults(ExportResults { class, file }) => {
+                use std::fmt::Write;
+
+                // quote every field, a nickname can contain anything,
+                // even something a spreadsheet would run as a formula
+                let field = |text: &str| {
+                    let text = text.replace('"', "\"\"");
+                    match text.starts_with(['=', '+', '-', '@']) {
+                        true => format!("\"'{text}\""),
+                        false => format!("\"{text}\""),
+                    }
+                };
+                let mut csv = String::from("profil,nickname,votes,author,description,vetoed\n");
+                for line in server.class_results(&class)? {
+                    writeln!(
+                        &mut csv,
+                        "{},{},{},{},{},{}",
+                        field(&line.target),
+                        field(&line.nickname),
+                        line.votes,
+                        field(&line.author),
+                        field(&line.description),
+                        line.vetoed
+                    )
+                    .unwrap();
+                }
+                match file {
+                    None => Ok(Some(csv)),
+                    // the command can come from the web, it must not write anywhere else
+                    Some(file)
+                        if file.is_empty()
+                            || file.starts_with('.')
+                            || file.contains(['/', '\\']) =>
+                    {
+                        Err(ServerError::InvalidFileName)
+                    }
+                    Some(file) => {
+                        let path = Path::new(EXPORT_DIRECTORY).join(&file);
+                        std::fs::create_dir_all(EXPORT_DIRECTORY)
+                            .and_then(|_| std::fs::write(&path, csv))
+                            .map_err(|e| {
+                                ServerError::WriteFailed(format!("{}: {e}", path.display()))
+                            })?;
+                        Ok(Some(format!("results written in {}", path.display())))
+                    }
+                }
+            }
+            Commands::ListTrash(ListTrash { name }) => {
+                use std::fmt::Write;
+
+                let id = name.map(|name| server.get_profil_id(&name)).transpose()?;
+                let lines = server.list_trash(id);
+                let mut output = String::new();
+                if lines.is_empty() {
+                    writeln!(&mut output, "The trash is empty!").unwrap();
+                }
+                let now = now();
+                for line in lines {
+                    writeln!(
+                        &mut output,
+                        "{}: \"{}\" with {} vote(s), deleted by {} {} day(s) ago",
+                        line.target,
+                        line.nickname,
+                        line.votes,
+                        line.deleted_by,
+                        now.saturating_sub(line.deleted_at) / (24 * 60 * 60)
+                    )
+                    .unwrap();
+                }
+                Ok(Some(output))
+            }
+            Commands::RestoreNickname(RestoreNickname { name, nickname }) => {
+                let id = server.get_profil_id(&name)?;
+                server.restore_nickname(id, &nickname).map(|_| None)
+            }
+            Commands::ViewArchive(ViewArchive { name }) => {
+                use std::fmt::Write;
+
+                let id = server.get_profil_id(&name)?;
+                let archives = server.archives_of(id);
+                let mut output = String::new();
+                if archives.is_empty() {
+                    writeln!(&mut output, "No archived round found!").unwrap();
+                }
+                let now = now();
+                for (round, archive) in archives.iter().enumerate() {
+                    writeln!(
+                        &mut output,
+                        "round {}, ended {} day(s) ago:",
+                        round + 1,
+                        now.saturating_sub(archive.ended_at) / (24 * 60 * 60)
+                    )
+                    .unwrap();
+                    for (nickname, count) in archive.results.iter() {
+                        writeln!(&mut output, "  {nickname}: {count}").unwrap();
+                    }
+                }
+                Ok(Some(output))
+            }
+            Commands::SetVotingMode(SetVotingMode { class, mode }) => {
+                let mode = match mode {
+                    VotingModeKind::Single => VotingMode::Single,
+                    VotingModeKind::Approval { max_votes } => VotingMode::Approval { max_votes },
+                    VotingModeKind::Points { points } => VotingMode::Points { points },
+                    VotingModeKind::Ranked => VotingMode::Ranked,
+                };
+                server.set_voting_mode(&class, mode).map(|_| None)
+            }
+            Commands::SetClassGroup(SetClassGroup { class, group }) => {
+                server.set_class_group(&class, group).map(|_| None)
+            }
             Commands::ChangePerm(ChangePermission { name, kind }) => {
                 let id = server.get_profil_id(&name)?;
                 let perm = server.get_permissions_mut(id)?;
@@ -212,7 +847,21 @@ impl AppState {
                     PermissionKind::Vote { permission } => perm.vote = permission,
                     PermissionKind::Delete { permission } => perm.delete = permission,
                     PermissionKind::Protect { permission } => perm.protect_nickname = permission,
+                    PermissionKind::Comment { permission } => perm.comment = permission,
+                    PermissionKind::ScopeClasses { classes } => perm.scope_classes = classes,
                     PermissionKind::UseCmd { permission } => perm.allowed_to_use_cmd = permission,
+                    PermissionKind::Role { role } => {
+                        if role
+                            .as_ref()
+                            .is_some_and(|role| !self.roles.contains_key(role))
+                        {
+                            return Ok(Some(format!(
+                                "This role does not exist, available roles are: {}",
+                                self.roles.keys().cloned().collect::<Vec<_>>().join(", ")
+                            )));
+                        }
+                        server.set_role(id, role)?;
+                    }
                 }
                 Ok(None)
             }
@@ -220,61 +869,102 @@ impl AppState {
     }
 }
 
-fn get_id(data_server: &DataServer, user: Option<actix_identity::Identity>) -> Option<ProfilID> {
-    let name = user?.id().ok()?;
-    data_server.get_profil_id(&name).ok()
-}
-
 #[actix_web::post("/login")]
 async fn login(
     login: web::Json<Login>,
     req: HttpRequest,
     state: web::Data<State>,
 ) -> impl Responder {
-    let server = &state.lock().unwrap().data_server;
+    let app = &mut *state.write().unwrap();
+    let name = &login.identity.name;
+    let ip = req.peer_addr().map(|addr| addr.ip());
+
+    if let Err(retry_after) = app.login_guard.check(name, ip) {
+        return Either::Right(
+            HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, retry_after.as_secs().max(1)))
+                .finish(),
+        );
+    }
+
+    let server = &mut app.data_server;
     let id = server.log(&login.identity);
     if id.is_some() {
-        actix_identity::Identity::login(&req.extensions(), login.identity.name.clone()).unwrap();
+        app.login_guard.record_success(name);
+        actix_identity::Identity::login(&req.extensions(), name.clone()).unwrap();
+    } else {
+        app.login_guard.record_failure(name, ip);
     };
-    web::Json(server.class_list(id))
+    Either::Left(web::Json(server.class_list(id)))
 }
 
 #[actix_web::post("/change_password")]
 async fn change_password(
     new_password: web::Json<c2s::ChangePassword>,
+    req: HttpRequest,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    requester: Requester,
 ) -> impl Responder {
-    let server = &mut state.lock().unwrap().data_server;
-    let Some(id) = get_id(&server, user) else {
+    let app = &mut *state.write().unwrap();
+    let Some(id) = requester.id(&app.data_server, Access::Write) else {
+        return HttpResponse::Unauthorized();
+    };
+    let Ok(name) = app.data_server.get_name(id) else {
         return HttpResponse::Unauthorized();
     };
-    if server
+    if app
+        .data_server
         .change_password(id, new_password.0.new_password)
         .is_ok()
     {
+        // log out every other device, the current session is renewed to stay logged
+        app.sessions.revoke(&name);
+        if let Requester::Session(_) = requester {
+            actix_identity::Identity::login(&req.extensions(), name).unwrap();
+        }
         HttpResponse::Ok()
     } else {
         HttpResponse::BadRequest()
     }
 }
 
+#[actix_web::post("/update_profile")]
+async fn update_profile(
+    profile: web::Json<c2s::UpdateProfile>,
+    state: web::Data<State>,
+    requester: Requester,
+    updates: web::Data<LiveUpdates>,
+) -> impl Responder {
+    let server = &mut state.write().unwrap().data_server;
+    let Some(id) = requester.id(server, Access::Write) else {
+        return Either::Right(HttpResponse::Unauthorized());
+    };
+    let c2s::UpdateProfile { display_name, bio } = profile.0;
+    let refusal = server.update_profile(id, display_name, bio).err();
+    // the public name shows up in the lists of the classmates
+    if refusal.is_none() {
+        for classmate in server.classmates_of(id) {
+            updates.notify(classmate);
+        }
+    }
+    let mut response = server.class_list(Some(id));
+    response.refusal = refusal;
+    Either::Left(web::Json(response))
+}
+
 #[actix_web::post("/logout")]
 async fn logout(state: web::Data<State>, user: Option<actix_identity::Identity>) -> impl Responder {
     if let Some(user) = user {
         user.logout();
     }
-    let server = &state.lock().unwrap().data_server;
+    let server = &state.read().unwrap().data_server;
     web::Json(server.class_list(None))
 }
 
 #[actix_web::get("/class_list")]
-async fn list_class(
-    state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
-) -> impl Responder {
-    let server = &state.lock().unwrap().data_server;
-    let id = get_id(&server, user);
+async fn list_class(state: web::Data<State>, requester: Requester) -> impl Responder {
+    let server = &state.read().unwrap().data_server;
+    let id = requester.id(server, Access::Read);
     web::Json(server.class_list(id))
 }
 
@@ -282,38 +972,254 @@ async fn list_class(
 async fn nickname_list(
     asked: web::Json<AskForNicknameList>,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    requester: Requester,
 ) -> impl Responder {
     let AskForNicknameList { profil } = asked.0;
-    let server = &state.lock().unwrap().data_server;
-    let id = get_id(&server, user);
+    let server = &state.read().unwrap().data_server;
+    let id = requester.id(server, Access::Read);
     web::Json(server.nickname_list(id, profil))
 }
 
+/// push the nickname list of a profil each time it changes
+#[actix_web::get("/nickname_events")]
+async fn nickname_events(
+    asked: web::Query<AskForNicknameList>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let AskForNicknameList { profil } = asked.into_inner();
+
+    // the requester is resolved on each event, so a deleted profil or a revoked token stop seeing privileged content
+    let events = updates.event_stream(profil, move || {
+        let server = &state.read().unwrap().data_server;
+        let id = requester.id(server, Access::Read);
+        serde_json::to_string(&server.nickname_list(id, profil)).ok()
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events)
+}
+
 #[actix_web::post("/profil_stats")]
 async fn profil_stats(
     asked: web::Json<AskForProfilStats>,
     state: web::Data<State>,
 ) -> impl Responder {
     let AskForProfilStats { profil } = asked.0;
-    let server = &state.lock().unwrap().data_server;
+    let server = &state.read().unwrap().data_server;
     match server.profil_stats(profil) {
         None => Either::Left(HttpResponse::BadRequest()),
         Some(s) => Either::Right(web::Json(s)),
     }
 }
 
+#[actix_web::get("/server_stats")]
+async fn server_stats(state: web::Data<State>, requester: Requester) -> impl Responder {
+    let app = &state.read().unwrap();
+    let Some(id) = requester.id(&app.data_server, Access::Read) else {
+        return Either::Right(HttpResponse::Unauthorized());
+    };
+
+    // the same content as the view-stats command
+    if app.authorize(id, "view-stats", None).is_err() {
+        return Either::Right(HttpResponse::Unauthorized());
+    };
+
+    Either::Left(web::Json(app.data_server.server_stats()))
+}
+
 #[actix_web::post("/vote_nickname")]
 async fn vote_nickname(
     vote_nickname: web::Json<VoteNickname>,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let VoteNickname {
+        target,
+        nickname,
+        points,
+    } = vote_nickname.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+    if let Some(id) = id {
+        let refusal = server.vote(id, target, nickname, points).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.nickname_list(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/rank_nicknames")]
+async fn rank_nicknames(
+    rank_nicknames: web::Json<RankNicknames>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
 ) -> impl Responder {
-    let VoteNickname { target, nickname } = vote_nickname.0;
-    let server = &mut state.lock().unwrap().data_server;
-    let id = get_id(&server, user);
+    let RankNicknames { target, ranking } = rank_nicknames.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
     if let Some(id) = id {
-        server.vote(id, target, nickname);
+        let refusal = server.rank(id, target, ranking).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.ranked_results(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/ranked_results")]
+async fn ranked_results(
+    asked: web::Json<AskForNicknameList>,
+    state: web::Data<State>,
+    requester: Requester,
+) -> impl Responder {
+    let AskForNicknameList { profil } = asked.0;
+    let server = &state.read().unwrap().data_server;
+    let id = requester.id(server, Access::Read);
+    web::Json(server.ranked_results(id, profil))
+}
+
+#[actix_web::post("/react_nickname")]
+async fn react_nickname(
+    react_nickname: web::Json<ReactNickname>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let ReactNickname {
+        target,
+        nickname,
+        reaction,
+    } = react_nickname.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+    if let Some(id) = id {
+        let refusal = server.react(id, target, nickname, reaction).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.nickname_list(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/veto_nickname")]
+async fn veto_nickname(
+    veto_nickname: web::Json<VetoNickname>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let VetoNickname {
+        target,
+        nickname,
+        vetoed,
+    } = veto_nickname.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+    if let Some(id) = id {
+        let refusal = server.veto(id, target, nickname, vetoed).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.nickname_list(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/describe_nickname")]
+async fn describe_nickname(
+    describe_nickname: web::Json<DescribeNickname>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let DescribeNickname {
+        target,
+        nickname,
+        description,
+    } = describe_nickname.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+    if let Some(id) = id {
+        let refusal = server.describe(id, target, nickname, description).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.nickname_list(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/comment_nickname")]
+async fn comment_nickname(
+    comment_nickname: web::Json<CommentNickname>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let CommentNickname {
+        target,
+        nickname,
+        text,
+    } = comment_nickname.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+    if let Some(id) = id {
+        let refusal = server.comment(id, target, nickname, text).err();
+        if refusal.is_none() {
+            updates.notify(target);
+        }
+        let mut response = server.nickname_list(Some(id), target);
+        response.refusal = refusal;
+        Either::Left(web::Json(response))
+    } else {
+        Either::Right(HttpResponse::Unauthorized())
+    }
+}
+
+#[actix_web::post("/delete_comment")]
+async fn delete_comment(
+    delete_comment: web::Json<DeleteComment>,
+    state: web::Data<State>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
+) -> impl Responder {
+    let DeleteComment {
+        target,
+        nickname,
+        comment,
+    } = delete_comment.0;
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
+
+    if let Some(id) = id {
+        if server.delete_comment(id, target, nickname, comment) {
+            updates.notify(target);
+        }
         Either::Left(web::Json(server.nickname_list(Some(id), target)))
     } else {
         Either::Right(HttpResponse::Unauthorized())
@@ -324,14 +1230,16 @@ async fn vote_nickname(
 async fn delete_nickname(
     delete_nickname: web::Json<DeleteNickname>,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
 ) -> impl Responder {
     let DeleteNickname { target, nickname } = delete_nickname.0;
-    let server = &mut state.lock().unwrap().data_server;
-    let id = get_id(&server, user);
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
 
     if let Some(id) = id {
         server.delete(id, target, nickname);
+        updates.notify(target);
         Either::Left(web::Json(server.nickname_list(Some(id), target)))
     } else {
         Either::Right(HttpResponse::Unauthorized())
@@ -342,18 +1250,20 @@ async fn delete_nickname(
 async fn update_protection_nickname(
     nickname_protection_update: web::Json<UpdateNicknameProtection>,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    updates: web::Data<LiveUpdates>,
+    requester: Requester,
 ) -> impl Responder {
     let UpdateNicknameProtection {
         target,
         nickname,
         protection_statut,
     } = nickname_protection_update.0;
-    let server = &mut state.lock().unwrap().data_server;
-    let id = get_id(&server, user);
+    let server = &mut state.write().unwrap().data_server;
+    let id = requester.id(server, Access::Write);
 
     if let Some(id) = id {
         server.update_nickname_protection(id, target, nickname, protection_statut);
+        updates.notify(target);
         Either::Left(web::Json(server.nickname_list(Some(id), target)))
     } else {
         Either::Right(HttpResponse::Unauthorized())
@@ -364,18 +1274,15 @@ async fn update_protection_nickname(
 async fn cmd_input(
     cmd: web::Json<CommandInput>,
     state: web::Data<State>,
-    user: Option<actix_identity::Identity>,
+    requester: Requester,
+    updates: web::Data<LiveUpdates>,
 ) -> impl Responder {
-    let app = &mut state.lock().unwrap();
-    let Some(id) = get_id(&app.data_server, user) else {
+    let app = &mut state.write().unwrap();
+    let Some((id, scope)) = requester.authenticate(&app.data_server) else {
         return Either::Right(HttpResponse::Unauthorized());
     };
 
-    if let Some(Permissions {
-        allowed_to_use_cmd: false,
-        ..
-    }) = app.data_server.get_permission(id)
-    {
+    if !app.is_privileged(id) {
         return Either::Right(HttpResponse::Unauthorized());
     };
 
@@ -386,9 +1293,12 @@ async fn cmd_input(
     };
 
     let clap = Commands::clap().setting(AppSettings::NoBinaryName);
-    let command = clap.get_matches_from_safe(inputs.iter().map(|input| input.trim()));
-    let command = match command {
-        Ok(command) => Commands::from_clap(&command),
+    let matches = clap.get_matches_from_safe(inputs.iter().map(|input| input.trim()));
+    let (command, command_name) = match matches {
+        Ok(matches) => (
+            Commands::from_clap(&matches),
+            matches.subcommand_name().unwrap_or_default().to_string(),
+        ),
         Err(e) => {
             return Either::Left(web::Json(CommandResponse {
                 text: e.to_string(),
@@ -396,7 +1306,17 @@ async fn cmd_input(
         }
     };
 
-    let result = app.execute_command(command);
+    if scope == TokenScope::ReadOnly && !command.is_read_only() {
+        return Either::Left(web::Json(CommandResponse {
+            text: "this token is read only, this command is not allowed".to_string(),
+        }));
+    }
+
+    let caller = Caller {
+        id,
+        command_name: &command_name,
+    };
+    let result = app.execute_command(command, Some(caller), &updates);
     let text = match result {
         Ok(None) => "action performed successfully!".to_string(),
         Ok(Some(result)) => result.trim().to_string(),
@@ -406,12 +1326,41 @@ async fn cmd_input(
     Either::Left(web::Json(CommandResponse { text }))
 }
 
-async fn save_loop(state: web::Data<Mutex<AppState>>, duration: Duration) {
+async fn save_loop(state: web::Data<State>, duration: Duration) {
     let mut interval = actix_web::rt::time::interval(duration);
     loop {
         interval.tick().await;
-        let mut state = state.lock().unwrap();
-        state.save()
+
+        let start = Instant::now();
+        let snapshot = {
+            let app = &mut *state.write().unwrap();
+            let purged = app.data_server.purge_trash();
+            if purged > 0 {
+                info!("{purged} deleted nickname(s) purged from the trash");
+            }
+            app.snapshot()
+        };
+        let locked = start.elapsed();
+        if snapshot.is_empty() {
+            continue;
+        }
+
+        // handlers keep running while the files are written
+        match spawn_blocking(move || snapshot.write()).await {
+            Ok(Ok(())) => info!(
+                "content saved in {:?}, state locked for {:?}",
+                start.elapsed(),
+                locked
+            ),
+            Ok(Err(e)) => {
+                error!("failed to save: {e}");
+                state.write().unwrap().mark_unsaved();
+            }
+            Err(e) => {
+                error!("save task failed: {e}");
+                state.write().unwrap().mark_unsaved();
+            }
+        }
     }
 }
 
@@ -423,16 +1372,134 @@ enum Commands {
     AddClass(AddClass),
     DeleteClass(DeleteClass),
     ViewLonelyPeople,
+    #[structopt(about = "View an overview of the whole server")]
+    ViewStats,
+    ViewInactive(ViewInactive),
     AddLonelyPeopleToClass(AddLonelyToClass),
     ViewPassword(ViewPassword),
     ChangePassword(ChangePassword),
     ChangeName(ChangeName),
     AddToClass(AddToClass),
     RemoveFromClass(RemoveFromClass),
+    AddClassAdmin(AddClassAdmin),
+    RemoveClassAdmin(RemoveClassAdmin),
     ChangePerm(ChangePermission),
+    ListTemplates,
+    EditTemplate(EditTemplate),
+    DeleteTemplate(DeleteTemplate),
+    ApplyTemplate(ApplyTemplate),
+    SetClassTemplate(SetClassTemplate),
+    SetClassGroup(SetClassGroup),
+    SetClassSelfVotes(SetClassSelfVotes),
+    SetVotingMode(SetVotingMode),
+    SetVoteVisibility(SetVoteVisibility),
+    SetClassBlind(SetClassBlind),
+    SetVetoBudget(SetVetoBudget),
+    RevealResults(RevealResults),
+    StartFinal(StartFinal),
+    ExportResults(ExportResults),
+    ViewArchive(ViewArchive),
+    ListTrash(ListTrash),
+    RestoreNickname(RestoreNickname),
+    ViewSelfVotes(ViewSelfVotes),
+    ListSessions(ListSessions),
+    RevokeSessions(RevokeSessions),
+    CreateToken(CreateToken),
+    ListTokens(ListTokens),
+    RevokeToken(RevokeToken),
+    UnlockAccount(UnlockAccount),
+    Benchmark(Benchmark),
 }
 
-fn wait_for_cmd_input(server: web::Data<Mutex<AppState>>) {
+impl Commands {
+    /// commands which only look at the state, the only ones allowed to read only tokens
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::ViewLonelyPeople
+                | Commands::ViewStats
+                | Commands::ViewInactive(_)
+                | Commands::ListSessions(_)
+                | Commands::ListTokens(_)
+                | Commands::ListTemplates
+                | Commands::ViewSelfVotes(ViewSelfVotes { purge: false })
+                | Commands::ViewArchive(_)
+                | Commands::ListTrash(_)
+                | Commands::ExportResults(ExportResults { file: None, .. })
+        )
+    }
+
+    fn targets(&self) -> Option<Targets<'_>> {
+        match self {
+            Commands::Exit
+            | Commands::Benchmark(_)
+            | Commands::AddClass(_)
+            | Commands::ViewLonelyPeople
+            | Commands::ViewStats
+            | Commands::AddLonelyPeopleToClass(_)
+            | Commands::ListTemplates
+            | Commands::EditTemplate(_)
+            | Commands::DeleteTemplate(_)
+            | Commands::ViewSelfVotes(_) => None,
+            // a new profil isn't anyone's yet
+            Commands::AddProfil(_) => Some(Targets::default()),
+            Commands::DeleteClass(DeleteClass { name })
+            | Commands::ApplyTemplate(ApplyTemplate { class: name, .. })
+            | Commands::SetClassTemplate(SetClassTemplate { class: name, .. })
+            | Commands::SetClassGroup(SetClassGroup { class: name, .. })
+            | Commands::SetClassSelfVotes(SetClassSelfVotes { class: name, .. })
+            | Commands::SetVotingMode(SetVotingMode { class: name, .. })
+            | Commands::SetVoteVisibility(SetVoteVisibility { class: name, .. })
+            | Commands::SetClassBlind(SetClassBlind { class: name, .. })
+            | Commands::SetVetoBudget(SetVetoBudget { class: name, .. })
+            | Commands::RevealResults(RevealResults { class: name })
+            | Commands::StartFinal(StartFinal { class: name, .. })
+            | Commands::ExportResults(ExportResults { class: name, .. }) => {
+                Some(Targets::classes(vec![name]))
+            }
+            Commands::ViewInactive(ViewInactive { class, .. }) => {
+                class.as_ref().map(|class| Targets::classes(vec![class]))
+            }
+            Commands::ListTrash(ListTrash { name }) => {
+                name.as_ref().map(|name| Targets::profils(vec![name]))
+            }
+            Commands::DeleteProfil(DeleteProfil { name })
+            | Commands::ViewPassword(ViewPassword { name })
+            | Commands::ChangePassword(ChangePassword { name, .. })
+            | Commands::ChangeName(ChangeName { name, .. })
+            | Commands::ChangePerm(ChangePermission { name, .. })
+            | Commands::ListSessions(ListSessions { name })
+            | Commands::RevokeSessions(RevokeSessions { name })
+            | Commands::CreateToken(CreateToken { name, .. })
+            | Commands::ListTokens(ListTokens { name })
+            | Commands::RevokeToken(RevokeToken { name, .. })
+            | Commands::ViewArchive(ViewArchive { name })
+            | Commands::RestoreNickname(RestoreNickname { name, .. })
+            | Commands::UnlockAccount(UnlockAccount { name }) => Some(Targets::profils(vec![name])),
+            Commands::AddToClass(AddToClass {
+                profil_name,
+                class_name,
+            })
+            | Commands::RemoveFromClass(RemoveFromClass {
+                profil_name,
+                class_name,
+            })
+            | Commands::AddClassAdmin(AddClassAdmin {
+                profil_name,
+                class_name,
+            })
+            | Commands::RemoveClassAdmin(RemoveClassAdmin {
+                profil_name,
+                class_name,
+            }) => Some(Targets {
+                profils: vec![profil_name],
+                classes: vec![class_name],
+            }),
+        }
+    }
+}
+
+fn wait_for_cmd_input(server: web::Data<State>, updates: web::Data<LiveUpdates>) {
     let mut command = String::new();
     loop {
         // read stdin
@@ -462,7 +1529,24 @@ fn wait_for_cmd_input(server: web::Data<Mutex<AppState>>) {
             return;
         }
 
-        let result = server.lock().unwrap().execute_command(command);
+        // the benchmark does not touch the state, don't block the server while it runs
+        if let Commands::Benchmark(Benchmark {
+            readers,
+            writers,
+            seconds,
+        }) = command
+        {
+            println!(
+                "{}",
+                benchmark::run(readers, writers, Duration::from_secs(seconds))
+            );
+            continue;
+        }
+
+        let result = server
+            .write()
+            .unwrap()
+            .execute_command(command, None, &updates);
         match result {
             Ok(None) => println!("action performed successfully!"),
             Ok(Some(result)) => println!("{}", result.trim()),
@@ -476,6 +1560,53 @@ struct ServerConfig {
     address: SocketAddr,
     save_intervals: Duration,
     save_format: SaveFormat,
+    #[serde(default)]
+    login_protection: LoginProtectionConfig,
+    #[serde(default)]
+    rate_limit: RateLimitConfig,
+    /// signing key of the session cookies, in base64, generated on the first start
+    #[serde(default)]
+    session_key: Option<String>,
+    #[serde(default = "ServerConfig::default_session_ttl")]
+    session_ttl: Duration,
+    /// roles which can be given to profils, with the commands they allow
+    #[serde(default = "roles::default_roles")]
+    roles: Roles,
+    /// whether people can propose and vote nicknames for themselves, classes can override it
+    #[serde(default)]
+    allow_self_votes: bool,
+    /// how long deleted nicknames can be restored
+    #[serde(default = "ServerConfig::default_trash_retention")]
+    trash_retention: Duration,
+}
+
+impl ServerConfig {
+    fn default_session_ttl() -> Duration {
+        Duration::from_secs(30 * 24 * 60 * 60)
+    }
+
+    fn default_trash_retention() -> Duration {
+        Duration::from_secs(30 * 24 * 60 * 60)
+    }
+
+    /// load the session key, or generate one and write it in the config so it survive restarts
+    fn session_key(&mut self) -> std::io::Result<Key> {
+        if let Some(key) = self
+            .session_key
+            .as_ref()
+            .and_then(|key| BASE64_STANDARD.decode(key).ok())
+            .and_then(|key| Key::try_from(key.as_slice()).ok())
+        {
+            return Ok(key);
+        }
+
+        let key = Key::generate();
+        self.session_key = Some(BASE64_STANDARD.encode(key.master()));
+        let config = File::create("config.json")?;
+        serde_json::to_writer_pretty(config, &self)?;
+        info!("Session key generated");
+        Ok(key)
+    }
 }
 
 impl Default for ServerConfig {
@@ -484,6 +1615,13 @@ impl Default for ServerConfig {
             address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 3000),
             save_intervals: Duration::from_secs(300),
             save_format: SaveFormat::Cbor,
+            login_protection: Default::default(),
+            rate_limit: Default::default(),
+            session_key: None,
+            session_ttl: Self::default_session_ttl(),
+            roles: roles::default_roles(),
+            allow_self_votes: false,
+            trash_retention: Self::default_trash_retention(),
         }
     }
 }
@@ -492,7 +1630,6 @@ impl Default for ServerConfig {
 async fn main() -> std::io::Result<()> {
     // install global subscriber configured based on RUST_LOG envvar.
     tracing_subscriber::fmt().init();
-    let secret_key = Key::generate();
 
     let Ok(file) = File::open("config.json") else {
         let config = File::create("config.json").expect("failed to create config");
@@ -500,18 +1637,35 @@ async fn main() -> std::io::Result<()> {
         info!("Config created");
         return Ok(());
     };
-    let config: ServerConfig = serde_json::from_reader(file)?;
+    let mut config: ServerConfig = serde_json::from_reader(file)?;
+    let secret_key = config.session_key()?;
 
     info!("Starting server");
 
-    let state = web::Data::new(AppState::new(config.save_format));
+    let state = web::Data::new(AppState::new(
+        config.save_format,
+        LoginGuard::new(config.login_protection),
+        config.roles.clone(),
+    ));
+    {
+        let data_server = &mut state.write().unwrap().data_server;
+        data_server.set_allow_self_votes(config.allow_self_votes);
+        data_server.set_trash_retention(config.trash_retention);
+    }
+
+    let rate_limiter = web::Data::new(RateLimiter::new(config.rate_limit));
+    let live_updates = web::Data::new(LiveUpdates::new());
+    let session_store = state.read().unwrap().sessions.clone();
+    let session_ttl = actix_web::cookie::time::Duration::try_from(config.session_ttl)
+        .expect("session ttl is too long");
 
     let cloned = state.clone();
     let cloned2 = state.clone();
+    let cloned_updates = live_updates.clone();
     tokio::spawn(save_loop(state.clone(), config.save_intervals));
 
     let signal = async || {
-        spawn_blocking(move || wait_for_cmd_input(cloned))
+        spawn_blocking(move || wait_for_cmd_input(cloned, cloned_updates))
             .await
             .unwrap();
     };
@@ -521,11 +1675,15 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .app_data(web::Data::clone(&state))
+            .app_data(web::Data::clone(&rate_limiter))
+            .app_data(web::Data::clone(&live_updates))
+            .wrap(from_fn(rate_limit::rate_limit))
             .wrap(IdentityMiddleware::default())
-            .wrap(SessionMiddleware::new(
-                CookieSessionStore::default(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(session_store.clone(), secret_key.clone())
+                    .session_lifecycle(PersistentSession::default().session_ttl(session_ttl))
+                    .build(),
+            )
             //.wrap(Logger::default())
             .wrap(cors)
             .configure(routes)
@@ -539,8 +1697,11 @@ async fn main() -> std::io::Result<()> {
     .await;
 
     info!("server stopping");
-    cloned2.lock().unwrap().save();
-    info!("content saved");
+    let snapshot = cloned2.write().unwrap().snapshot();
+    match snapshot.write() {
+        Ok(()) => info!("content saved"),
+        Err(e) => error!("failed to save: {e}"),
+    }
     e
 }
 
@@ -548,11 +1709,21 @@ fn routes(cfg: &mut ServiceConfig) {
     cfg.service(login);
     cfg.service(logout);
     cfg.service(change_password);
+    cfg.service(update_profile);
     cfg.service(list_class);
     cfg.service(nickname_list);
+    cfg.service(nickname_events);
     cfg.service(profil_stats);
+    cfg.service(server_stats);
     cfg.service(delete_nickname);
+    cfg.service(veto_nickname);
     cfg.service(vote_nickname);
+    cfg.service(rank_nicknames);
+    cfg.service(ranked_results);
+    cfg.service(react_nickname);
+    cfg.service(describe_nickname);
+    cfg.service(comment_nickname);
+    cfg.service(delete_comment);
     cfg.service(update_protection_nickname);
     cfg.service(cmd_input);
 }
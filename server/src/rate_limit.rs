@@ -0,0 +1,152 @@
+use crate::requester::Requester;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct BucketConfig {
+    /// how many requests can be made at once
+    pub burst: u32,
+    /// how many requests are given back each second
+    pub per_second: f64,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// limit of each logged user or api token
+    pub identity: BucketConfig,
+    /// limit of each ip, a whole school can share an ip
+    pub ip: BucketConfig,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            identity: BucketConfig {
+                burst: 30,
+                per_second: 5.0,
+            },
+            ip: BucketConfig {
+                burst: 300,
+                per_second: 50.0,
+            },
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Clone, Hash, Eq, PartialEq)]
+enum Key {
+    Identity(String),
+    Ip(std::net::IpAddr),
+}
+
+/// Token bucket rate limiter, it has its own lock so throttled requests never touch the app state
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<Key, Bucket>>,
+}
+
+impl RateLimiter {
+    /// over this amount of buckets, full ones are forgotten
+    const MAX_BUCKETS: usize = 4096;
+
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// take a token from every given key, return how long to wait if one of them is empty
+    fn take(&self, keys: Vec<Key>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > Self::MAX_BUCKETS {
+            let config = self.config;
+            buckets.retain(|key, bucket| {
+                let config = Self::bucket_config(&config, key);
+                Self::refill(bucket, config, now);
+                bucket.tokens < config.burst as f64
+            });
+        }
+
+        let mut wait = Duration::ZERO;
+        for key in keys.iter() {
+            let config = Self::bucket_config(&self.config, key);
+            let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+                tokens: config.burst as f64,
+                last_refill: now,
+            });
+            Self::refill(bucket, config, now);
+            if bucket.tokens < 1.0 {
+                let missing = (1.0 - bucket.tokens) / config.per_second;
+                wait = wait.max(Duration::from_secs_f64(missing));
+            }
+        }
+
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for key in keys.iter() {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    fn bucket_config(config: &RateLimitConfig, key: &Key) -> BucketConfig {
+        match key {
+            Key::Identity(_) => config.identity,
+            Key::Ip(_) => config.ip,
+        }
+    }
+
+    fn refill(bucket: &mut Bucket, config: BucketConfig, now: Instant) {
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = f64::min(
+            bucket.tokens + elapsed * config.per_second,
+            config.burst as f64,
+        );
+        bucket.last_refill = now;
+    }
+}
+
+/// Middleware answering 429 with a Retry-After header to clients hammering the server
+pub async fn rate_limit<B: MessageBody + 'static>(
+    limiter: web::Data<RateLimiter>,
+    requester: Requester,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let mut keys = Vec::with_capacity(2);
+    if let Some(key) = requester.rate_limit_key() {
+        keys.push(Key::Identity(key));
+    }
+    if let Some(addr) = req.peer_addr() {
+        keys.push(Key::Ip(addr.ip()));
+    }
+
+    match limiter.take(keys) {
+        Ok(()) => next.call(req).await.map(|res| res.map_into_left_body()),
+        Err(wait) => {
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((RETRY_AFTER, wait.as_secs_f64().ceil().max(1.0) as u64))
+                .finish();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
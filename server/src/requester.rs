@@ -0,0 +1,73 @@
+use crate::data_server::api_tokens::{ApiToken, TokenScope};
+use crate::data_server::DataServer;
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{FromRequest, HttpRequest};
+use common::ProfilID;
+use std::future::{ready, Ready};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// How a request authenticated itself, the profil behind it is only resolved once the state is locked
+pub enum Requester {
+    Anonymous,
+    /// logged through the session cookie, holds the profil name
+    Session(String),
+    /// api token given as a bearer in the Authorization header
+    Token(String),
+}
+
+impl Requester {
+    /// find the profil making the request, sessions have every right a token can have
+    pub fn authenticate(&self, server: &DataServer) -> Option<(ProfilID, TokenScope)> {
+        match self {
+            Requester::Anonymous => None,
+            Requester::Session(name) => server
+                .get_profil_id(name)
+                .ok()
+                .map(|id| (id, TokenScope::Full)),
+            Requester::Token(token) => server.authenticate_token(token),
+        }
+    }
+
+    /// same as authenticate, but read only tokens are refused when the request modify something
+    pub fn id(&self, server: &DataServer, access: Access) -> Option<ProfilID> {
+        let (id, scope) = self.authenticate(server)?;
+        (access == Access::Read || scope == TokenScope::Full).then_some(id)
+    }
+
+    /// what the rate limiter should count requests against, tokens are hashed to not keep them around
+    pub fn rate_limit_key(&self) -> Option<String> {
+        match self {
+            Requester::Anonymous => None,
+            Requester::Session(name) => Some(name.clone()),
+            Requester::Token(token) => Some(ApiToken::hash(token)),
+        }
+    }
+}
+
+impl FromRequest for Requester {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let bearer = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "));
+        if let Some(token) = bearer {
+            return ready(Ok(Requester::Token(token.trim().to_string())));
+        }
+
+        let name = actix_identity::Identity::from_request(req, payload)
+            .into_inner()
+            .ok()
+            .and_then(|user| user.id().ok());
+        ready(Ok(name.map_or(Requester::Anonymous, Requester::Session)))
+    }
+}
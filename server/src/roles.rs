@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A named set of console commands someone can use from the client
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Role {
+    /// commands names as typed in the console, "*" allows every command
+    pub commands: Vec<String>,
+    /// commands can only target the classes the user belongs to, and the people in them
+    #[serde(default)]
+    pub class_scoped: bool,
+}
+
+impl Role {
+    /// role given to profils which only have the old `allowed_to_use_cmd` permission
+    pub const LEGACY: &'static str = "superadmin";
+
+    pub fn allows(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c == "*" || c == command)
+    }
+
+    pub fn is_superadmin(&self) -> bool {
+        !self.class_scoped && self.commands.iter().any(|c| c == "*")
+    }
+
+    fn new(commands: &[&str], class_scoped: bool) -> Self {
+        Self {
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            class_scoped,
+        }
+    }
+}
+
+pub type Roles = HashMap<String, Role>;
+
+pub fn default_roles() -> Roles {
+    HashMap::from([
+        (Role::LEGACY.to_string(), Role::new(&["*"], false)),
+        (
+            "moderator".to_string(),
+            Role::new(
+                &[
+                    "view-lonely-people",
+                    "view-stats",
+                    "view-inactive",
+                    "list-sessions",
+                    "revoke-sessions",
+                    "unlock-account",
+                ],
+                false,
+            ),
+        ),
+        (
+            "class-manager".to_string(),
+            Role::new(
+                &[
+                    "add-profil",
+                    "add-to-class",
+                    "remove-from-class",
+                    "change-password",
+                    "view-inactive",
+                    "list-sessions",
+                    "revoke-sessions",
+                    "unlock-account",
+                ],
+                true,
+            ),
+        ),
+    ])
+}
+
+/// What a command acts on, so class scoped roles can be checked
+#[derive(Default)]
+pub struct Targets<'a> {
+    pub profils: Vec<&'a String>,
+    pub classes: Vec<&'a String>,
+}
+
+impl<'a> Targets<'a> {
+    pub fn profils(profils: Vec<&'a String>) -> Self {
+        Self {
+            profils,
+            classes: vec![],
+        }
+    }
+
+    pub fn classes(classes: Vec<&'a String>) -> Self {
+        Self {
+            profils: vec![],
+            classes,
+        }
+    }
+}
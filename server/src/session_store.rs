@@ -0,0 +1,170 @@
+use crate::data_server::mutation_tracker::MutationTracker;
+use crate::data_server::now;
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// key used by actix_identity to store who is logged in a session
+const IDENTITY_KEY: &str = "actix_identity.user_id";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredSession {
+    state: HashMap<String, String>,
+    /// unix timestamp
+    expires_at: u64,
+}
+
+impl StoredSession {
+    /// name of the logged profil, if any
+    fn owner(&self) -> Option<String> {
+        let id = self.state.get(IDENTITY_KEY)?;
+        serde_json::from_str(id).ok()
+    }
+}
+
+/// sessions by the sha256 of their key, in hex, so the saved file can't be used to log in
+pub type Sessions = HashMap<String, StoredSession>;
+
+/// Server-side session storage, saved along the rest of the data so sessions survive a restart, and can be revoked
+#[derive(Clone, Default)]
+pub struct PersistentSessionStore {
+    sessions: Arc<Mutex<MutationTracker<Sessions>>>,
+}
+
+impl PersistentSessionStore {
+    pub fn new(sessions: Sessions) -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(MutationTracker::new(sessions))),
+        }
+    }
+
+    fn generate_key() -> String {
+        Alphanumeric.sample_string(&mut rand::rng(), 64)
+    }
+
+    fn hash(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    fn expiry(ttl: &Duration) -> u64 {
+        now() + ttl.whole_seconds().max(0) as u64
+    }
+
+    /// the sessions will be written again on the next save
+    pub fn mark_unsaved(&self) {
+        self.sessions.lock().unwrap().mark_dirty();
+    }
+
+    /// return the sessions if they changed since the last save, expired sessions are dropped on the way
+    pub fn try_to_save(&self) -> Option<Sessions> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = now();
+        if sessions.values().any(|session| session.expires_at <= now) {
+            sessions.retain(|_, session| session.expires_at > now);
+        }
+
+        if sessions.clear_dirty() {
+            Some(Sessions::clone(&sessions))
+        } else {
+            None
+        }
+    }
+
+    /// list the expiry date of every active session of a profil, with the beginning of their hash to tell them apart
+    pub fn list(&self, name: &str) -> Vec<(String, u64)> {
+        let sessions = self.sessions.lock().unwrap();
+        let now = now();
+        let mut list: Vec<_> = sessions
+            .iter()
+            .filter(|(_, session)| session.expires_at > now)
+            .filter(|(_, session)| session.owner().is_some_and(|owner| owner == name))
+            .map(|(hash, session)| (hash.chars().take(8).collect(), session.expires_at))
+            .collect();
+        list.sort_by_key(|(_, expires_at)| *expires_at);
+        list
+    }
+
+    /// log out every session of a profil, return how many were revoked
+    pub fn revoke(&self, name: &str) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.owner().is_none_or(|owner| owner != name));
+        before - sessions.len()
+    }
+}
+
+impl SessionStore for PersistentSessionStore {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .get(&Self::hash(session_key.as_ref()))
+            .filter(|session| session.expires_at > now())
+            .map(|session| session.state.clone()))
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let key = Self::generate_key();
+        let session = StoredSession {
+            state: session_state,
+            expires_at: Self::expiry(ttl),
+        };
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(Self::hash(&key), session);
+        key.try_into()
+            .map_err(|e| SaveError::Other(anyhow::Error::new(e)))
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        mut session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&Self::hash(session_key.as_ref())) {
+            session.state = session_state;
+            session.expires_at = Self::expiry(ttl);
+            return Ok(session_key);
+        }
+
+        // the session got revoked during the request, it must not come back to life
+        session_state.remove(IDENTITY_KEY);
+        let key = Self::generate_key();
+        let session = StoredSession {
+            state: session_state,
+            expires_at: Self::expiry(ttl),
+        };
+        sessions.insert(Self::hash(&key), session);
+        key.try_into()
+            .map_err(|e| UpdateError::Other(anyhow::Error::new(e)))
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let hash = Self::hash(session_key.as_ref());
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&hash) {
+            session.expires_at = Self::expiry(ttl);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .remove(&Self::hash(session_key.as_ref()));
+        Ok(())
+    }
+}